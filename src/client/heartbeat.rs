@@ -0,0 +1,307 @@
+//! websocket 心跳：定期发送 Ping 帧或文本、回复服务端的 ping、长时间没有消息时判定连接失效
+//!
+//! ```ignore
+//! // 每 15 秒发送 {"op":"ping"}，回复服务端的 {"ping":123}，30 秒没有消息认为连接失效
+//! let hb = Heartbeat::new()
+//!     .ping_text(Duration::from_secs(15), r#"{"op":"ping"}"#)
+//!     .answer_json("ping", "pong")
+//!     .stale_after(Duration::from_secs(30));
+//!
+//! // 单独使用
+//! let mut ws = HeartbeatWs::new(connect_ws(url).await?.0, hb.clone());
+//! while let Some(msg) = ws.next().await {
+//!     let msg = msg?;
+//! }
+//! // 在自动重连的连接中使用，连接失效时重连
+//! ManagedWs::spawn(ManagedWsConfig::new(url).heartbeat(hb));
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{Map, Value};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::time::Instant;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+type Responder = Arc<dyn Fn(&Message) -> Option<Message> + Send + Sync>;
+
+/// 心跳间隔的最小值，更小的间隔（包括 0）按该值处理
+pub const MIN_PING_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 主动发送的心跳内容
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PingKind {
+    /// 协议层的 Ping 帧
+    Frame,
+    /// 应用层的文本，例如 `{"op":"ping"}`
+    Text(String),
+}
+
+/// 心跳配置，默认不发送心跳、不检查超时
+#[derive(Clone, Default)]
+pub struct Heartbeat {
+    ping: Option<(Duration, PingKind)>,
+    responder: Option<Responder>,
+    stale_after: Option<Duration>,
+}
+
+impl fmt::Debug for Heartbeat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Heartbeat")
+            .field("ping", &self.ping)
+            .field("responder", &self.responder.is_some())
+            .field("stale_after", &self.stale_after)
+            .finish()
+    }
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 每隔 interval 发送 Ping 帧，interval 不小于 [`MIN_PING_INTERVAL`]
+    pub fn ping_frame(mut self, interval: Duration) -> Self {
+        self.ping = Some((interval.max(MIN_PING_INTERVAL), PingKind::Frame));
+        self
+    }
+
+    /// 每隔 interval 发送文本，interval 不小于 [`MIN_PING_INTERVAL`]
+    pub fn ping_text(mut self, interval: Duration, text: impl Into<String>) -> Self {
+        self.ping = Some((interval.max(MIN_PING_INTERVAL), PingKind::Text(text.into())));
+        self
+    }
+
+    /// 自定义服务端 ping 的回复，返回 Some 时发送回复，该消息不再交给调用方
+    pub fn answer(
+        mut self,
+        f: impl Fn(&Message) -> Option<Message> + Send + Sync + 'static,
+    ) -> Self {
+        self.responder = Some(Arc::new(f));
+        self
+    }
+
+    /// 收到内容为 ping 的文本时回复 pong
+    pub fn answer_text(self, ping: impl Into<String>, pong: impl Into<String>) -> Self {
+        let (ping, pong) = (ping.into(), pong.into());
+        self.answer(move |msg| match msg {
+            Message::Text(t) if t.trim() == ping => Some(Message::Text(pong.clone())),
+            _ => None,
+        })
+    }
+
+    /// 收到 `{"<ping_key>": v}` 时回复 `{"<pong_key>": v}`
+    pub fn answer_json(self, ping_key: impl Into<String>, pong_key: impl Into<String>) -> Self {
+        let (ping_key, pong_key) = (ping_key.into(), pong_key.into());
+        self.answer(move |msg| {
+            let text = match msg {
+                Message::Text(t) if t.contains(&ping_key) => t,
+                _ => return None,
+            };
+            let v = serde_json::from_str::<Map<String, Value>>(text)
+                .ok()?
+                .remove(&ping_key)?;
+            let mut pong = Map::new();
+            pong.insert(pong_key.clone(), v);
+            Some(Message::Text(Value::Object(pong).to_string()))
+        })
+    }
+
+    /// 超过 timeout 没有收到任何消息（包括 Pong 帧）时认为连接失效
+    pub fn stale_after(mut self, timeout: Duration) -> Self {
+        self.stale_after = Some(timeout);
+        self
+    }
+
+    /// 需要回复的消息返回回复内容
+    pub fn reply(&self, msg: &Message) -> Option<Message> {
+        self.responder.as_ref().and_then(|f| f(msg))
+    }
+
+    /// 心跳计时，从当前时间开始
+    pub fn timer(&self) -> HeartbeatTimer {
+        let now = Instant::now();
+        HeartbeatTimer {
+            ping: self.ping.clone(),
+            next_ping: self.ping.as_ref().map(|(d, _)| now + *d),
+            stale_after: self.stale_after,
+            deadline: self.stale_after.map(|d| now + d),
+        }
+    }
+}
+
+/// tick 的结果
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tick {
+    /// 需要发送心跳
+    Ping(Message),
+    /// 超过该时间没有收到消息
+    Stale(Duration),
+}
+
+/// 心跳计时，用于在自己的读取循环中加入心跳
+#[derive(Debug)]
+pub struct HeartbeatTimer {
+    ping: Option<(Duration, PingKind)>,
+    next_ping: Option<Instant>,
+    stale_after: Option<Duration>,
+    deadline: Option<Instant>,
+}
+
+impl HeartbeatTimer {
+    /// 收到消息后调用，重新计算超时
+    pub fn received(&mut self) {
+        if let Some(d) = self.stale_after {
+            self.deadline = Some(Instant::now() + d);
+        }
+    }
+
+    /// 等待下一次需要发送心跳或连接超时；可以在 select! 中使用，取消不影响计时
+    pub async fn tick(&mut self) -> Tick {
+        match (self.next_ping, self.deadline) {
+            (Some(at), deadline) if !matches!(deadline, Some(d) if d <= at) => {
+                tokio::time::sleep_until(at).await;
+                let (interval, kind) = self.ping.as_ref().expect("ping configured");
+                self.next_ping = Some(Instant::now() + *interval);
+                Tick::Ping(match kind {
+                    PingKind::Frame => Message::Ping(Vec::new()),
+                    PingKind::Text(t) => Message::Text(t.clone()),
+                })
+            }
+            (_, Some(deadline)) => {
+                tokio::time::sleep_until(deadline).await;
+                Tick::Stale(self.stale_after.unwrap_or_default())
+            }
+            _ => std::future::pending().await,
+        }
+    }
+}
+
+/// 带心跳的 websocket 连接
+pub struct HeartbeatWs<S> {
+    ws: WebSocketStream<S>,
+    heartbeat: Heartbeat,
+    timer: HeartbeatTimer,
+}
+
+impl<S> HeartbeatWs<S>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    pub fn new(ws: WebSocketStream<S>, heartbeat: Heartbeat) -> Self {
+        let timer = heartbeat.timer();
+        Self {
+            ws,
+            heartbeat,
+            timer,
+        }
+    }
+
+    /// 下一条消息，期间自动发送心跳、回复服务端 ping；已回复的消息不会返回，
+    /// 超时未收到消息时返回错误，连接结束时返回 None
+    pub async fn next(&mut self) -> Option<Result<Message>> {
+        loop {
+            tokio::select! {
+                msg = self.ws.next() => {
+                    let msg = match msg? {
+                        Ok(msg) => msg,
+                        Err(err) => return Some(Err(err.into())),
+                    };
+                    self.timer.received();
+                    match self.heartbeat.reply(&msg) {
+                        Some(pong) => {
+                            if let Err(err) = self.ws.send(pong).await {
+                                return Some(Err(err.into()));
+                            }
+                        }
+                        None => return Some(Ok(msg)),
+                    }
+                }
+                tick = self.timer.tick() => match tick {
+                    Tick::Ping(ping) => {
+                        if let Err(err) = self.ws.send(ping).await {
+                            return Some(Err(err.into()));
+                        }
+                    }
+                    Tick::Stale(d) => return Some(Err(anyhow!("超过 {:?} 未收到消息", d))),
+                },
+            }
+        }
+    }
+
+    pub async fn send(&mut self, msg: Message) -> Result<()> {
+        Ok(self.ws.send(msg).await?)
+    }
+
+    pub fn get_mut(&mut self) -> &mut WebSocketStream<S> {
+        &mut self.ws
+    }
+
+    pub fn into_inner(self) -> WebSocketStream<S> {
+        self.ws
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::websocket::connect_ws;
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    #[test]
+    fn answer_json() {
+        let hb = Heartbeat::new().answer_json("ping", "pong");
+        assert_eq!(
+            hb.reply(&Message::Text(r#"{"ping":1700000000000}"#.into())),
+            Some(Message::Text(r#"{"pong":1700000000000}"#.into()))
+        );
+        assert_eq!(hb.reply(&Message::Text(r#"{"ch":"ping"}"#.into())), None);
+        assert_eq!(hb.reply(&Message::Ping(Vec::new())), None);
+    }
+
+    #[tokio::test]
+    async fn zero_interval_is_clamped() {
+        let mut timer = Heartbeat::new().ping_frame(Duration::ZERO).timer();
+        let start = Instant::now();
+        timer.tick().await;
+        timer.tick().await;
+        assert!(start.elapsed() >= MIN_PING_INTERVAL * 2);
+    }
+
+    #[tokio::test]
+    async fn ping_answer_and_stale() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            let ping = ws.next().await.unwrap().unwrap();
+            ws.send(Message::Text(r#"{"ping":7}"#.into()))
+                .await
+                .unwrap();
+            let pong = ws.next().await.unwrap().unwrap();
+            done.send((ping, pong)).unwrap();
+            // 之后不再发送消息，保持连接
+            while ws.next().await.is_some() {}
+        });
+
+        let (ws, _) = connect_ws(format!("ws://{}", addr)).await.unwrap();
+        let hb = Heartbeat::new()
+            .ping_text(Duration::from_millis(20), "ping")
+            .answer_json("ping", "pong")
+            .stale_after(Duration::from_millis(300));
+        let mut ws = HeartbeatWs::new(ws, hb);
+        assert!(ws.next().await.unwrap().is_err());
+
+        let (ping, pong) = rx.await.unwrap();
+        assert_eq!(ping, Message::Text("ping".into()));
+        assert_eq!(pong, Message::Text(r#"{"pong":7}"#.into()));
+    }
+}
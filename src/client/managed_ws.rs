@@ -0,0 +1,486 @@
+//! 自动重连的 websocket 连接：断开后按退避时间重连，重连后重新发送订阅消息
+//!
+//! ```ignore
+//! let mut ws = ManagedWs::spawn(
+//!     ManagedWsConfig::new("wss://stream.example.com/ws")
+//!         .subscribe(Message::Text(r#"{"op":"subscribe","args":["ticker"]}"#.into())),
+//! );
+//! while let Some(msg) = ws.recv().await {
+//!     // 处理行情
+//! }
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use futures_util::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::{debug, info, warn};
+
+use super::heartbeat::{Heartbeat, Tick};
+use super::websocket::{connect_ws_with_options, WsConnectOptions};
+use crate::tool::req::RetryPolicy;
+
+type Ws = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// 连接状态
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WsState {
+    /// 首次连接中
+    Connecting,
+    Connected,
+    /// 连接断开，等待 delay 后进行第 attempt 次重连
+    Reconnecting {
+        attempt: u32,
+        delay: Duration,
+    },
+    /// 已关闭，不再重连
+    Closed,
+}
+
+#[derive(Debug, Clone)]
+pub struct ManagedWsConfig {
+    pub url: String,
+    /// 指定连接的 ip:port，不填时按域名连接
+    pub addr: Option<String>,
+    /// 代理、各阶段超时、请求头等连接参数
+    pub options: WsConnectOptions,
+    /// 每次连接成功后依次发送
+    pub subscribe: Vec<Message>,
+    /// 整个连接过程的超时时间，各阶段的超时见 options
+    pub connect_timeout: Duration,
+    pub min_backoff: Duration,
+    pub max_backoff: Duration,
+    /// 连续重连失败的最大次数，None 表示一直重连
+    pub max_retries: Option<u32>,
+    /// 接收消息的队列长度；队列满时最多再暂存 capacity 条，期间心跳、发送不受影响，
+    /// 超出后断开重连
+    pub capacity: usize,
+    /// 心跳配置，连接失效时重连
+    pub heartbeat: Heartbeat,
+}
+
+impl ManagedWsConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            addr: None,
+            options: WsConnectOptions::default(),
+            subscribe: Vec::new(),
+            connect_timeout: Duration::from_secs(10),
+            min_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_retries: None,
+            capacity: 1024,
+            heartbeat: Heartbeat::default(),
+        }
+    }
+
+    pub fn addr(mut self, addr: impl Into<String>) -> Self {
+        self.addr = Some(addr.into());
+        self
+    }
+
+    /// 代理，格式与 tool::req 相同
+    pub fn proxy(mut self, proxy: &Option<String>) -> Self {
+        self.options.proxy = proxy.clone();
+        self
+    }
+
+    pub fn options(mut self, options: WsConnectOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn subscribe(mut self, msg: Message) -> Self {
+        self.subscribe.push(msg);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    pub fn backoff(mut self, min: Duration, max: Duration) -> Self {
+        self.min_backoff = min;
+        self.max_backoff = max;
+        self
+    }
+
+    pub fn max_retries(mut self, max: u32) -> Self {
+        self.max_retries = Some(max);
+        self
+    }
+
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    pub fn heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.heartbeat = heartbeat;
+        self
+    }
+
+    async fn connect(&self) -> Result<Ws> {
+        let conn = connect_ws_with_options(self.url.as_str(), self.addr.as_deref(), &self.options);
+        let (ws, _) = tokio::time::timeout(self.connect_timeout, conn)
+            .await
+            .map_err(|_| anyhow!("连接 {} 超时", self.url))??;
+        Ok(ws)
+    }
+}
+
+#[derive(Debug)]
+enum Command {
+    Send(Message),
+    Subscribe(Message),
+    Close,
+}
+
+/// 后台任务持有连接，收到的 Text / Binary 消息通过 recv 读取
+#[derive(Debug)]
+pub struct ManagedWs {
+    messages: mpsc::Receiver<Message>,
+    state: watch::Receiver<WsState>,
+    cmd: mpsc::UnboundedSender<Command>,
+    task: JoinHandle<()>,
+}
+
+impl ManagedWs {
+    pub fn spawn(config: ManagedWsConfig) -> Self {
+        let (tx, messages) = mpsc::channel(config.capacity);
+        let (state_tx, state) = watch::channel(WsState::Connecting);
+        let (cmd, cmd_rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(run(config, tx, state_tx, cmd_rx));
+        Self {
+            messages,
+            state,
+            cmd,
+            task,
+        }
+    }
+
+    /// 下一条消息，连接关闭且不再重连时返回 None
+    pub async fn recv(&mut self) -> Option<Message> {
+        self.messages.recv().await
+    }
+
+    /// 连接状态，可以 changed().await 等待变化
+    pub fn state(&self) -> watch::Receiver<WsState> {
+        self.state.clone()
+    }
+
+    /// 通过当前连接发送，未连接时丢弃
+    pub fn send(&self, msg: Message) -> Result<()> {
+        self.command(Command::Send(msg))
+    }
+
+    /// 追加订阅：立即发送，并在之后每次重连时重新发送
+    pub fn subscribe(&self, msg: Message) -> Result<()> {
+        self.command(Command::Subscribe(msg))
+    }
+
+    /// 关闭连接，不再重连
+    pub fn close(&self) {
+        let _ = self.command(Command::Close);
+    }
+
+    fn command(&self, cmd: Command) -> Result<()> {
+        self.cmd.send(cmd).map_err(|_| anyhow!("websocket 已关闭"))
+    }
+}
+
+impl Drop for ManagedWs {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+// 连接 -> 读取直到断开 -> 退避 -> 重连
+async fn run(
+    config: ManagedWsConfig,
+    tx: mpsc::Sender<Message>,
+    state: watch::Sender<WsState>,
+    mut cmd: mpsc::UnboundedReceiver<Command>,
+) {
+    let policy = RetryPolicy::new(u32::MAX).backoff(config.min_backoff, config.max_backoff);
+    let mut subscribe = config.subscribe.clone();
+    let mut attempt = 0;
+
+    loop {
+        match config.connect().await {
+            Ok(mut ws) => {
+                info!("websocket {} 已连接", config.url);
+                attempt = 0;
+                state.send_replace(WsState::Connected);
+                match serve(&mut ws, &config, &tx, &mut subscribe, &mut cmd).await {
+                    Serve::Closed => {
+                        let _ = ws.close(None).await;
+                        break;
+                    }
+                    Serve::Disconnected(reason) => {
+                        warn!("websocket {} 断开：{}", config.url, reason)
+                    }
+                }
+            }
+            Err(err) => warn!("websocket {} 连接失败：{}", config.url, err),
+        }
+
+        attempt += 1;
+        if matches!(config.max_retries, Some(max) if attempt > max) {
+            warn!(
+                "websocket {} 重连 {} 次失败，不再重连",
+                config.url,
+                attempt - 1
+            );
+            break;
+        }
+        let delay = policy.backoff_for(attempt);
+        state.send_replace(WsState::Reconnecting { attempt, delay });
+        if !wait(delay, &mut subscribe, &mut cmd).await {
+            break;
+        }
+    }
+    state.send_replace(WsState::Closed);
+}
+
+enum Serve {
+    /// 调用方关闭
+    Closed,
+    Disconnected(String),
+}
+
+async fn serve(
+    ws: &mut Ws,
+    config: &ManagedWsConfig,
+    tx: &mpsc::Sender<Message>,
+    subscribe: &mut Vec<Message>,
+    cmd: &mut mpsc::UnboundedReceiver<Command>,
+) -> Serve {
+    let heartbeat = &config.heartbeat;
+    for msg in subscribe.iter() {
+        if let Err(err) = ws.send(msg.clone()).await {
+            return Serve::Disconnected(format!("发送订阅失败：{}", err));
+        }
+    }
+
+    let mut timer = heartbeat.timer();
+    // 接收方处理不过来时暂存消息，期间仍然读取连接，回复服务端的 Ping；
+    // 暂存的消息也达到 capacity 时断开重连，不丢弃消息
+    let mut pending = VecDeque::<Message>::new();
+    loop {
+        tokio::select! {
+            msg = ws.next() => {
+                let msg = match msg {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(err)) => return Serve::Disconnected(err.to_string()),
+                    None => return Serve::Disconnected("连接已结束".to_string()),
+                };
+                timer.received();
+                if let Some(pong) = heartbeat.reply(&msg) {
+                    if let Err(err) = ws.send(pong).await {
+                        return Serve::Disconnected(format!("回复心跳失败：{}", err));
+                    }
+                    continue;
+                }
+                if let Message::Close(frame) = msg {
+                    return Serve::Disconnected(format!("服务端关闭：{:?}", frame));
+                }
+                if !matches!(msg, Message::Text(_) | Message::Binary(_)) {
+                    continue;
+                }
+                if pending.is_empty() {
+                    match tx.try_send(msg) {
+                        Ok(()) => continue,
+                        Err(mpsc::error::TrySendError::Full(msg)) => pending.push_back(msg),
+                        // 接收方已释放
+                        Err(mpsc::error::TrySendError::Closed(_)) => return Serve::Closed,
+                    }
+                } else if pending.len() >= config.capacity {
+                    return Serve::Disconnected(format!("接收方处理过慢，已暂存 {} 条消息", pending.len()));
+                } else {
+                    pending.push_back(msg);
+                }
+            }
+            permit = tx.reserve(), if !pending.is_empty() => match (permit, pending.pop_front()) {
+                (Ok(permit), Some(msg)) => permit.send(msg),
+                _ => return Serve::Closed,
+            },
+            tick = timer.tick() => match tick {
+                Tick::Ping(ping) => {
+                    if let Err(err) = ws.send(ping).await {
+                        return Serve::Disconnected(format!("发送心跳失败：{}", err));
+                    }
+                }
+                Tick::Stale(d) => return Serve::Disconnected(format!("超过 {:?} 未收到消息", d)),
+            },
+            c = cmd.recv() => {
+                let msg = match c {
+                    Some(Command::Send(msg)) => msg,
+                    Some(Command::Subscribe(msg)) => {
+                        subscribe.push(msg.clone());
+                        msg
+                    }
+                    Some(Command::Close) | None => return Serve::Closed,
+                };
+                if let Err(err) = ws.send(msg).await {
+                    return Serve::Disconnected(format!("发送失败：{}", err));
+                }
+            }
+        }
+    }
+}
+
+// 退避等待，期间收到的订阅在重连后发送；返回 false 表示已关闭
+async fn wait(
+    delay: Duration,
+    subscribe: &mut Vec<Message>,
+    cmd: &mut mpsc::UnboundedReceiver<Command>,
+) -> bool {
+    let sleep = tokio::time::sleep(delay);
+    tokio::pin!(sleep);
+    loop {
+        tokio::select! {
+            _ = &mut sleep => return true,
+            c = cmd.recv() => match c {
+                Some(Command::Subscribe(msg)) => subscribe.push(msg),
+                Some(Command::Send(msg)) => debug!("websocket 未连接，丢弃消息：{:?}", msg),
+                Some(Command::Close) | None => return false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // 每个连接回复收到的前两条消息后断开
+    async fn server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                for _ in 0..2 {
+                    if let Some(Ok(Message::Text(t))) = ws.next().await {
+                        ws.send(Message::Text(format!("echo:{}", t))).await.unwrap();
+                    }
+                }
+                ws.close(None).await.ok();
+            }
+        });
+        format!("ws://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn reconnect_and_resubscribe() {
+        let url = server().await;
+        let mut ws = ManagedWs::spawn(
+            ManagedWsConfig::new(url)
+                .subscribe(Message::Text("a".into()))
+                .backoff(Duration::from_millis(10), Duration::from_millis(20)),
+        );
+        let mut state = ws.state();
+
+        assert_eq!(ws.recv().await, Some(Message::Text("echo:a".into())));
+        ws.subscribe(Message::Text("b".into())).unwrap();
+        assert_eq!(ws.recv().await, Some(Message::Text("echo:b".into())));
+        // 服务端断开后重连，订阅按顺序重新发送
+        assert_eq!(ws.recv().await, Some(Message::Text("echo:a".into())));
+        assert_eq!(ws.recv().await, Some(Message::Text("echo:b".into())));
+
+        ws.close();
+        while *state.borrow_and_update() != WsState::Closed {
+            state.changed().await.unwrap();
+        }
+        assert!(ws.send(Message::Text("c".into())).is_err());
+    }
+
+    #[tokio::test]
+    async fn reconnect_when_stale() {
+        // 连接后不发送任何消息
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                tokio::spawn(async move { while ws.next().await.is_some() {} });
+            }
+        });
+        let ws = ManagedWs::spawn(
+            ManagedWsConfig::new(format!("ws://{}", addr))
+                .heartbeat(Heartbeat::new().stale_after(Duration::from_millis(50)))
+                .backoff(Duration::from_millis(10), Duration::from_millis(10)),
+        );
+        let mut state = ws.state();
+        state.wait_for(|s| *s == WsState::Connected).await.unwrap();
+        state
+            .wait_for(|s| matches!(s, WsState::Reconnecting { .. }))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn give_up_after_max_retries() {
+        // 没有监听的端口
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let mut ws = ManagedWs::spawn(
+            ManagedWsConfig::new(format!("ws://{}", addr))
+                .backoff(Duration::from_millis(1), Duration::from_millis(1))
+                .max_retries(2),
+        );
+        assert_eq!(ws.recv().await, None);
+        assert_eq!(*ws.state().borrow(), WsState::Closed);
+    }
+
+    #[tokio::test]
+    async fn slow_receiver_still_answers_ping() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+            for i in 0..4 {
+                ws.send(Message::Text(i.to_string())).await.unwrap();
+            }
+            ws.send(Message::Ping(b"hb".to_vec())).await.unwrap();
+            let pong = ws.next().await.unwrap().unwrap();
+            let msg = ws.next().await.unwrap().unwrap();
+            done.send((pong, msg)).unwrap();
+            while ws.next().await.is_some() {}
+        });
+
+        let mut ws = ManagedWs::spawn(ManagedWsConfig::new(format!("ws://{}", addr)).capacity(2));
+        ws.state()
+            .wait_for(|s| *s == WsState::Connected)
+            .await
+            .unwrap();
+        // 不读取消息，队列已满时仍然回复 Ping、可以发送
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        ws.send(Message::Text("x".into())).unwrap();
+        let (pong, msg) = tokio::time::timeout(Duration::from_secs(1), rx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(pong, Message::Pong(b"hb".to_vec()));
+        assert_eq!(msg, Message::Text("x".into()));
+        for i in 0..4 {
+            assert_eq!(ws.recv().await, Some(Message::Text(i.to_string())));
+        }
+        assert_eq!(*ws.state().borrow(), WsState::Connected);
+    }
+}
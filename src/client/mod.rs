@@ -1,3 +1,6 @@
 pub mod block_ws;
+pub mod heartbeat;
+pub mod managed_ws;
 pub mod price_ceiling;
+pub mod proxy;
 pub mod websocket;
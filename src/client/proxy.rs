@@ -0,0 +1,275 @@
+//! websocket 连接代理：建立到目标地址的 tcp 隧道，之后再进行 tls / ws 握手
+//!
+//! 代理格式与 tool::req 相同，见 [`ProxyTarget`]；websocket 不支持 `https://` 代理
+
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+
+use data_encoding::BASE64;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{lookup_host, TcpSocket, TcpStream};
+use tokio_socks::tcp::Socks5Stream;
+
+use crate::tool::req::ProxyTarget;
+
+// CONNECT 响应头的最大长度
+const MAX_RESPONSE_HEAD: usize = 8 * 1024;
+
+/// 解析 websocket 使用的代理，不支持的协议（包括 `https://`）返回错误
+pub fn parse_tcp_proxy(proxy: &str) -> io::Result<ProxyTarget> {
+    let target = ProxyTarget::parse(proxy)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+    match &target {
+        ProxyTarget::Remote { scheme, .. }
+            if !matches!(scheme.as_str(), "http" | "socks5" | "socks5h") =>
+        {
+            Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("websocket 不支持 {} 代理：{}", scheme, proxy),
+            ))
+        }
+        _ => Ok(target),
+    }
+}
+
+/// 连接到 host:port，proxy 为 None 时直连
+pub async fn connect_tcp(host: &str, port: u16, proxy: &Option<String>) -> io::Result<TcpStream> {
+    let target = match proxy {
+        Some(p) => parse_tcp_proxy(p)?,
+        None => return TcpStream::connect((host, port)).await,
+    };
+    match target {
+        ProxyTarget::Local(ip) => connect_from(ip, host, port).await,
+        ProxyTarget::Remote {
+            scheme,
+            host: p_host,
+            port: p_port,
+            auth,
+        } => match scheme.as_str() {
+            "http" => {
+                let mut stream = TcpStream::connect((p_host.as_str(), p_port)).await?;
+                http_connect(&mut stream, host, port, &auth).await?;
+                Ok(stream)
+            }
+            "socks5" | "socks5h" => {
+                let proxy = (p_host.as_str(), p_port);
+                // socks5 在本地解析域名，socks5h 由代理解析
+                let stream = match (scheme.as_str(), auth) {
+                    ("socks5", auth) => {
+                        let addr = resolve(host, port, None).await?;
+                        socks5(proxy, addr, auth).await
+                    }
+                    (_, auth) => socks5(proxy, (host, port), auth).await,
+                };
+                stream.map(Socks5Stream::into_inner).map_err(socks_err)
+            }
+            s => unreachable!("parse_tcp_proxy 已拒绝 {} 代理", s),
+        },
+    }
+}
+
+// 绑定本地出口 ip 后连接，只使用与出口 ip 相同协议族的地址
+async fn connect_from(ip: IpAddr, host: &str, port: u16) -> io::Result<TcpStream> {
+    let addr = resolve(host, port, Some(ip)).await?;
+    let socket = match ip {
+        IpAddr::V4(_) => TcpSocket::new_v4()?,
+        IpAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    socket.bind(SocketAddr::new(ip, 0))?;
+    socket.connect(addr).await
+}
+
+// 解析域名，指定 local 时只返回与其协议族相同的地址
+async fn resolve(host: &str, port: u16, local: Option<IpAddr>) -> io::Result<SocketAddr> {
+    let addrs = lookup_host((host, port)).await?.collect::<Vec<_>>();
+    if addrs.is_empty() {
+        let msg = format!("无法解析域名 {}", host);
+        return Err(io::Error::new(io::ErrorKind::NotFound, msg));
+    }
+    let local = match local {
+        Some(ip) => ip,
+        None => return Ok(addrs[0]),
+    };
+    same_family(&addrs, local).ok_or_else(|| {
+        let family = if local.is_ipv4() { "IPv4" } else { "IPv6" };
+        let msg = format!("{} 没有 {} 地址，无法从 {} 连接", host, family, local);
+        io::Error::new(io::ErrorKind::AddrNotAvailable, msg)
+    })
+}
+
+fn same_family(addrs: &[SocketAddr], local: IpAddr) -> Option<SocketAddr> {
+    addrs
+        .iter()
+        .find(|a| a.is_ipv4() == local.is_ipv4())
+        .copied()
+}
+
+async fn socks5<'t, T>(
+    proxy: (&str, u16),
+    target: T,
+    auth: Option<(String, String)>,
+) -> tokio_socks::Result<Socks5Stream<TcpStream>>
+where
+    T: tokio_socks::IntoTargetAddr<'t>,
+{
+    match auth {
+        Some((user, pass)) => {
+            Socks5Stream::connect_with_password(proxy, target, &user, &pass).await
+        }
+        None => Socks5Stream::connect(proxy, target).await,
+    }
+}
+
+fn socks_err(err: tokio_socks::Error) -> io::Error {
+    match err {
+        tokio_socks::Error::Io(err) => err,
+        err => io::Error::other(format!("socks5 代理连接失败：{}", err)),
+    }
+}
+
+// 发送 CONNECT 请求并读取响应头，成功后 stream 即为到目标地址的隧道
+async fn http_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    auth: &Option<(String, String)>,
+) -> io::Result<()> {
+    let target = match host.parse::<IpAddr>() {
+        Ok(IpAddr::V6(ip)) => format!("[{}]:{}", ip, port),
+        _ => format!("{}:{}", host, port),
+    };
+    let mut req = format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n", target);
+    if let Some((user, pass)) = auth {
+        let token = BASE64.encode(format!("{}:{}", user, pass).as_bytes());
+        req.push_str(&format!("Proxy-Authorization: Basic {}\r\n", token));
+    }
+    req.push_str("\r\n");
+    stream.write_all(req.as_bytes()).await?;
+
+    // 逐字节读取，避免读到隧道中的数据
+    let mut head = Vec::with_capacity(128);
+    while !head.ends_with(b"\r\n\r\n") {
+        if head.len() >= MAX_RESPONSE_HEAD {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "代理响应头过长"));
+        }
+        let mut b = [0u8; 1];
+        if stream.read(&mut b).await? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "代理关闭了连接",
+            ));
+        }
+        head.push(b[0]);
+    }
+
+    let head = String::from_utf8_lossy(&head);
+    let status = head.lines().next().unwrap_or_default();
+    match status.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::new(
+            io::ErrorKind::ConnectionRefused,
+            format!("代理 CONNECT 失败：{}", status),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    // 只处理一个连接的 CONNECT 代理，返回代理地址和收到的请求头
+    async fn connect_proxy() -> (String, tokio::sync::oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut client, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            while !head.ends_with(b"\r\n\r\n") {
+                head.push(client.read_u8().await.unwrap());
+            }
+            let head = String::from_utf8(head).unwrap();
+            let target = head.split_whitespace().nth(1).unwrap().to_string();
+            tx.send(head).unwrap();
+            let mut upstream = TcpStream::connect(target).await.unwrap();
+            client
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")
+                .await
+                .unwrap();
+            tokio::io::copy_bidirectional(&mut client, &mut upstream)
+                .await
+                .ok();
+        });
+        (format!("http://u%40x:p@{}", addr), rx)
+    }
+
+    async fn echo_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut s, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let (mut r, mut w) = s.split();
+                    tokio::io::copy(&mut r, &mut w).await.ok();
+                });
+            }
+        });
+        addr
+    }
+
+    async fn roundtrip(mut s: TcpStream) -> Vec<u8> {
+        s.write_all(b"hi").await.unwrap();
+        let mut buf = [0u8; 2];
+        s.read_exact(&mut buf).await.unwrap();
+        buf.to_vec()
+    }
+
+    #[tokio::test]
+    async fn http_connect_tunnel() {
+        let target = echo_server().await;
+        let (proxy, head) = connect_proxy().await;
+        let s = connect_tcp("127.0.0.1", target.port(), &Some(proxy))
+            .await
+            .unwrap();
+        assert_eq!(roundtrip(s).await, b"hi");
+
+        let head = head.await.unwrap();
+        assert!(head.starts_with(&format!("CONNECT 127.0.0.1:{} HTTP/1.1", target.port())));
+        // u@x:p
+        assert!(head.contains("Proxy-Authorization: Basic dUB4OnA="));
+    }
+
+    #[tokio::test]
+    async fn local_bind() {
+        let target = echo_server().await;
+        let s = connect_tcp(
+            "127.0.0.1",
+            target.port(),
+            &Some("local://127.0.0.1".into()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(s.local_addr().unwrap().ip().to_string(), "127.0.0.1");
+        assert_eq!(roundtrip(s).await, b"hi");
+
+        let err = connect_tcp("127.0.0.1", target.port(), &Some("ftp://1.1.1.1".into())).await;
+        assert!(err.is_err());
+        // 出口 ip 为 IPv4 时不能连接 IPv6 地址
+        let err = connect_tcp("::1", target.port(), &Some("local://127.0.0.1".into())).await;
+        assert_eq!(err.unwrap_err().kind(), io::ErrorKind::AddrNotAvailable);
+    }
+
+    #[test]
+    fn pick_same_family() {
+        let addrs = ["[::1]:443".parse().unwrap(), "1.2.3.4:443".parse().unwrap()];
+        let v4 = same_family(&addrs, "10.0.0.1".parse().unwrap());
+        assert_eq!(v4, Some(addrs[1]));
+        let v6 = same_family(&addrs[1..], "::2".parse().unwrap());
+        assert_eq!(v6, None);
+
+        let err = parse_tcp_proxy("https://p:443").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+        assert!(parse_tcp_proxy("socks5h://p").is_ok());
+    }
+}
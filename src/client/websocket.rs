@@ -1,11 +1,33 @@
 use anyhow::Result;
 use futures_util::TryFutureExt;
-use std::time::Duration;
+use rustls::ServerName;
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_tungstenite::tungstenite::http::header::{
+    HeaderName, HeaderValue, SEC_WEBSOCKET_PROTOCOL,
+};
 use tokio_tungstenite::tungstenite::{
-    client::IntoClientRequest, error::UrlError, handshake::client::Response, Error as WsError,
+    client::IntoClientRequest,
+    error::UrlError,
+    handshake::client::{Request, Response},
+    http, Error as WsError,
 };
 use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use tracing::debug;
+
+use super::proxy::{connect_tcp, parse_tcp_proxy};
+use crate::tool::probe::Prober;
+use crate::tool::req::staggered_first_ok;
+use crate::tool::tls::{default_tls_options, TlsOptions};
+
+/// 各阶段默认的超时时间
+pub const DEFAULT_WS_TIMEOUT: Duration = Duration::from_secs(10);
 
 /// 连接到 ws url地址
 pub async fn connect_ws<R>(
@@ -15,26 +37,13 @@ where
     R: IntoClientRequest + Unpin,
 {
     let request = url.into_client_request()?;
-    let domain = match request.uri().host() {
-        Some(d) => d.to_string(),
-        None => return Err(WsError::Url(UrlError::NoHostName)),
-    };
-    let port = request
-        .uri()
-        .port_u16()
-        .or_else(|| match request.uri().scheme_str() {
-            Some("wss") => Some(443),
-            Some("ws") => Some(80),
-            _ => None,
-        })
-        .ok_or(WsError::Url(UrlError::UnsupportedUrlScheme))?;
-
+    let (domain, port) = target_of(&request).map_err(WsError::Url)?;
     let addr = format!("{}:{}", domain, port);
 
     connect_ws_with_addr(request, addr).await
 }
 
-//连接ws url地址，指定连接到ip
+//连接ws url地址，指定连接到ip，各阶段使用默认的超时时间
 pub async fn connect_ws_with_addr<R>(
     request: R,
     addr: String,
@@ -42,19 +51,570 @@ pub async fn connect_ws_with_addr<R>(
 where
     R: IntoClientRequest + Unpin,
 {
-    let request = request.into_client_request()?;
-    let inbound = TcpStream::connect(addr).await.map_err(WsError::Io)?;
-    inbound.set_nodelay(true)?;
-    tokio_tungstenite::client_async_tls(request, inbound).await
+    connect_ws_with_tls(request, addr, &default_tls_options()).await
+}
+
+//连接到 prober 测得最快的地址，没有可用地址时按域名连接
+pub async fn connect_ws_fastest<R>(
+    url: R,
+    prober: &Prober,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let request = url.into_client_request()?;
+    let host = request.uri().host().unwrap_or_default().to_string();
+    match prober.best_addr(&host) {
+        Some(addr) => connect_ws_with_addr(request, addr.to_string()).await,
+        None => connect_ws(request).await,
+    }
+}
+
+//连接ws url地址，指定连接到ip，使用指定的 TLS 配置
+pub async fn connect_ws_with_tls<R>(
+    request: R,
+    addr: String,
+    tls: &TlsOptions,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let options = WsConnectOptions::default().tls(tls.clone());
+    Ok(connect_ws_with_options(request, Some(&addr), &options).await?)
+}
+
+/// 通过代理连接 ws url，代理格式与 tool::req 相同：
+/// `socks5://`、`socks5h://`、`http://`（可带 `user:pass@`）、`local://<ip>`，None 时直连
+pub async fn connect_ws_with_proxy<R>(
+    url: R,
+    proxy: &Option<String>,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let options = WsConnectOptions::default().proxy(proxy);
+    Ok(connect_ws_with_options(url, None, &options).await?)
+}
+
+/// 经过代理连接到 host:port 后进行 tls / ws 握手，host 可以是 url 之外的地址
+pub async fn connect_ws_via<R>(
+    request: R,
+    host: &str,
+    port: u16,
+    proxy: &Option<String>,
+    tls: &TlsOptions,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), WsError>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let addr = match host.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, port).to_string(),
+        Err(_) => format!("{}:{}", host, port),
+    };
+    let options = WsConnectOptions::default().proxy(proxy).tls(tls.clone());
+    Ok(connect_ws_with_options(request, Some(&addr), &options).await?)
+}
+
+/// 连接的阶段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WsPhase {
+    /// 解析 url、设置请求头
+    Request,
+    /// tcp 连接，使用代理时包括代理握手
+    Tcp,
+    Tls,
+    /// http 升级为 websocket
+    Upgrade,
+}
+
+impl fmt::Display for WsPhase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            WsPhase::Request => "构建请求",
+            WsPhase::Tcp => "tcp 连接",
+            WsPhase::Tls => "tls 握手",
+            WsPhase::Upgrade => "websocket 握手",
+        };
+        f.write_str(s)
+    }
+}
+
+/// 连接失败，包含失败的阶段
+#[derive(Debug)]
+pub enum WsConnectError {
+    Timeout {
+        phase: WsPhase,
+        after: Duration,
+    },
+    Failed {
+        phase: WsPhase,
+        source: Box<WsError>,
+    },
+}
+
+impl WsConnectError {
+    pub fn phase(&self) -> WsPhase {
+        match self {
+            WsConnectError::Timeout { phase, .. } | WsConnectError::Failed { phase, .. } => *phase,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, WsConnectError::Timeout { .. })
+    }
+
+    fn failed(phase: WsPhase, err: impl Into<WsError>) -> Self {
+        WsConnectError::Failed {
+            phase,
+            source: Box::new(err.into()),
+        }
+    }
+}
+
+impl fmt::Display for WsConnectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WsConnectError::Timeout { phase, after } => write!(f, "{} 超时（{:?}）", phase, after),
+            WsConnectError::Failed { phase, source } => write!(f, "{} 失败：{}", phase, source),
+        }
+    }
+}
+
+impl std::error::Error for WsConnectError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WsConnectError::Timeout { .. } => None,
+            WsConnectError::Failed { source, .. } => Some(source.as_ref()),
+        }
+    }
+}
+
+// 兼容返回 WsError 的旧接口，超时转换为 TimedOut 的 io 错误
+impl From<WsConnectError> for WsError {
+    fn from(err: WsConnectError) -> Self {
+        match err {
+            WsConnectError::Failed { source, .. } => *source,
+            timeout => WsError::Io(io::Error::new(io::ErrorKind::TimedOut, timeout.to_string())),
+        }
+    }
+}
+
+/// websocket 连接参数，各阶段的超时时间分开计算，None 表示不限制
+#[derive(Debug, Clone)]
+pub struct WsConnectOptions {
+    pub tcp_timeout: Option<Duration>,
+    pub tls_timeout: Option<Duration>,
+    pub upgrade_timeout: Option<Duration>,
+    /// 额外的请求头
+    pub headers: Vec<(String, String)>,
+    /// Sec-WebSocket-Protocol
+    pub subprotocols: Vec<String>,
+    /// 代理，格式与 tool::req 相同，不支持 `https://`
+    pub proxy: Option<String>,
+    pub tls: TlsOptions,
+    pub nodelay: bool,
+}
+
+impl Default for WsConnectOptions {
+    fn default() -> Self {
+        Self {
+            tcp_timeout: Some(DEFAULT_WS_TIMEOUT),
+            tls_timeout: Some(DEFAULT_WS_TIMEOUT),
+            upgrade_timeout: Some(DEFAULT_WS_TIMEOUT),
+            headers: Vec::new(),
+            subprotocols: Vec::new(),
+            proxy: None,
+            tls: default_tls_options(),
+            nodelay: true,
+        }
+    }
+}
+
+impl WsConnectOptions {
+    pub fn tcp_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.tcp_timeout = timeout;
+        self
+    }
+
+    pub fn tls_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.tls_timeout = timeout;
+        self
+    }
+
+    pub fn upgrade_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.upgrade_timeout = timeout;
+        self
+    }
+
+    /// 三个阶段使用相同的超时时间
+    pub fn timeout(self, timeout: Duration) -> Self {
+        self.tcp_timeout(Some(timeout))
+            .tls_timeout(Some(timeout))
+            .upgrade_timeout(Some(timeout))
+    }
+
+    pub fn header(mut self, k: &str, v: &str) -> Self {
+        self.headers.push((k.to_string(), v.to_string()));
+        self
+    }
+
+    pub fn subprotocol(mut self, protocol: &str) -> Self {
+        self.subprotocols.push(protocol.to_string());
+        self
+    }
+
+    pub fn proxy(mut self, proxy: &Option<String>) -> Self {
+        self.proxy = proxy.clone();
+        self
+    }
+
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    pub fn nodelay(mut self, nodelay: bool) -> Self {
+        self.nodelay = nodelay;
+        self
+    }
+}
+
+/// 按参数连接 ws url，addr 指定时连接到该 ip:port，失败时返回出错的阶段
+pub async fn connect_ws_with_options<R>(
+    url: R,
+    addr: Option<&str>,
+    options: &WsConnectOptions,
+) -> Result<(WebSocketStream<MaybeTlsStream<TcpStream>>, Response), WsConnectError>
+where
+    R: IntoClientRequest + Unpin,
+{
+    let (request, domain, host, port) = run_phase(WsPhase::Request, None, async {
+        let mut request = url.into_client_request()?;
+        let headers = request.headers_mut();
+        for (k, v) in options.headers.iter() {
+            let name = HeaderName::from_bytes(k.as_bytes()).map_err(http::Error::from)?;
+            headers.append(name, HeaderValue::from_str(v).map_err(http::Error::from)?);
+        }
+        if !options.subprotocols.is_empty() {
+            let value = HeaderValue::from_str(&options.subprotocols.join(", "))
+                .map_err(http::Error::from)?;
+            headers.insert(SEC_WEBSOCKET_PROTOCOL, value);
+        }
+
+        // 代理配置错误时直接失败，不进入连接阶段
+        if let Some(p) = &options.proxy {
+            parse_tcp_proxy(p)?;
+        }
+        let (domain, port) = target_of(&request)?;
+        let domain = domain
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let (host, port) = match addr {
+            Some(addr) => {
+                split_addr(addr).ok_or_else(|| UrlError::UnableToConnect(addr.to_string()))?
+            }
+            None => (domain.clone(), port),
+        };
+        Ok::<_, WsError>((request, domain, host, port))
+    })
+    .await?;
+
+    let tcp = run_phase(WsPhase::Tcp, options.tcp_timeout, async {
+        let tcp = connect_tcp(&host, port, &options.proxy).await?;
+        tcp.set_nodelay(options.nodelay)?;
+        Ok::<_, io::Error>(tcp)
+    })
+    .await?;
+
+    let stream = match request.uri().scheme_str() {
+        Some("wss") => {
+            let tls = run_phase(WsPhase::Tls, options.tls_timeout, async {
+                let config = options
+                    .tls
+                    .client_config()
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+                let name = ServerName::try_from(domain.as_str())
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err.to_string()))?;
+                TlsConnector::from(Arc::new(config))
+                    .connect(name, tcp)
+                    .await
+            })
+            .await?;
+            MaybeTlsStream::Rustls(tls)
+        }
+        _ => MaybeTlsStream::Plain(tcp),
+    };
+
+    run_phase(
+        WsPhase::Upgrade,
+        options.upgrade_timeout,
+        tokio_tungstenite::client_async_with_config(request, stream, None),
+    )
+    .await
+}
+
+/// 竞速连接的结果
+#[derive(Debug)]
+pub struct RacedWs {
+    pub ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    pub response: Response,
+    /// 胜出的地址 ip:port
+    pub addr: String,
+    /// 胜出连接从开始连接到握手完成的耗时
+    pub latency: Duration,
+}
+
+/// 同时连接多个地址，每隔 stagger 多发起一个，返回最先完成握手的连接，其余连接会被丢弃
+///
+/// addrs 可以直接使用 `hosts::split_txt` 的结果，不带端口时使用 url 的端口；
+/// 正在进行的连接全部失败时立即连接下一个地址，所有地址都失败时返回最后一个错误
+pub async fn connect_ws_race<S: AsRef<str>>(
+    url: &str,
+    addrs: &[S],
+    stagger: Duration,
+    options: &WsConnectOptions,
+) -> Result<RacedWs, WsConnectError> {
+    let port = run_phase(WsPhase::Request, None, async {
+        let (_, port) = target_of(&url.into_client_request()?)?;
+        Ok::<_, WsError>(port)
+    })
+    .await?;
+    let addrs = addrs
+        .iter()
+        .map(|a| with_port(a.as_ref().trim(), port))
+        .collect::<Vec<_>>();
+
+    let rs = staggered_first_ok(addrs.len(), stagger, |i| {
+        let addr = addrs[i].as_str();
+        async move {
+            let start = Instant::now();
+            match connect_ws_with_options(url, Some(addr), options).await {
+                Ok((ws, response)) => Ok((ws, response, start.elapsed())),
+                Err(err) => {
+                    debug!("websocket {} 连接 {} 失败：{}", url, addr, err);
+                    Err(err)
+                }
+            }
+        }
+    })
+    .await;
+    match rs {
+        Some(Ok((i, (ws, response, latency)))) => {
+            debug!(
+                "websocket {} 连接 {} 胜出，耗时 {:?}",
+                url, addrs[i], latency
+            );
+            Ok(RacedWs {
+                ws,
+                response,
+                addr: addrs[i].clone(),
+                latency,
+            })
+        }
+        Some(Err(err)) => Err(err),
+        None => {
+            let err = UrlError::UnableToConnect(format!("{} 没有可用的地址", url));
+            Err(WsConnectError::failed(WsPhase::Request, err))
+        }
+    }
+}
+
+// 不带端口的 ip 或域名补上端口
+fn with_port(addr: &str, port: u16) -> String {
+    if addr.parse::<SocketAddr>().is_ok() {
+        return addr.to_string();
+    }
+    match addr.parse::<IpAddr>() {
+        Ok(ip) => SocketAddr::new(ip, port).to_string(),
+        Err(_) if addr.contains(':') => addr.to_string(),
+        Err(_) => format!("{}:{}", addr, port),
+    }
 }
 
+// 按阶段计时，超时或失败时记录阶段
+async fn run_phase<T, E>(
+    phase: WsPhase,
+    timeout: Option<Duration>,
+    fut: impl Future<Output = Result<T, E>>,
+) -> Result<T, WsConnectError>
+where
+    E: Into<WsError>,
+{
+    let rs = match timeout {
+        Some(after) => tokio::time::timeout(after, fut)
+            .await
+            .map_err(|_| WsConnectError::Timeout { phase, after })?,
+        None => fut.await,
+    };
+    rs.map_err(|err| WsConnectError::failed(phase, err))
+}
+
+// 解析 ip:port、[ipv6]:port 或 域名:port
+fn split_addr(addr: &str) -> Option<(String, u16)> {
+    if let Ok(a) = addr.parse::<SocketAddr>() {
+        return Some((a.ip().to_string(), a.port()));
+    }
+    let (host, port) = addr.rsplit_once(':')?;
+    Some((host.to_string(), port.parse().ok()?))
+}
+
+// url 中的域名和端口，未指定端口时按协议取默认值
+fn target_of(request: &Request) -> Result<(String, u16), UrlError> {
+    let domain = match request.uri().host() {
+        Some(d) => d.to_string(),
+        None => return Err(UrlError::NoHostName),
+    };
+    let port = request
+        .uri()
+        .port_u16()
+        .or_else(|| match request.uri().scheme_str() {
+            Some("wss") => Some(443),
+            Some("ws") => Some(80),
+            _ => None,
+        })
+        .ok_or(UrlError::UnsupportedUrlScheme)?;
+    Ok((domain, port))
+}
+
+/// 整个连接过程使用同一个超时时间 d，不再按阶段计时
 pub async fn connect_to_ws_with_timeout(
     url: &str,
     d: Duration,
 ) -> Result<WebSocketStream<MaybeTlsStream<TcpStream>>> {
-    let (socket, _) = tokio::time::timeout(d, connect_ws(url).map_err(anyhow::Error::from))
+    let options = WsConnectOptions::default()
+        .tcp_timeout(None)
+        .tls_timeout(None)
+        .upgrade_timeout(None);
+    let conn = connect_ws_with_options(url, None, &options).map_err(anyhow::Error::from);
+    let (socket, _) = tokio::time::timeout(d, conn)
         .map_err(|_| anyhow::anyhow!("timeout"))
         .await
         .and_then(std::convert::identity)?;
     Ok(socket)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{
+        Request as ServerRequest, Response as ServerResponse,
+    };
+
+    // 接受连接但不做任何响应
+    async fn silent_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok((s, _)) = listener.accept().await {
+                conns.push(s);
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn whole_timeout() {
+        let addr = silent_server().await;
+        let err = connect_to_ws_with_timeout(&format!("ws://{}", addr), Duration::from_millis(50))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "timeout");
+    }
+
+    #[tokio::test]
+    async fn phase_timeout() {
+        let addr = silent_server().await;
+        let options = WsConnectOptions::default().timeout(Duration::from_millis(100));
+
+        let err = connect_ws_with_options(format!("ws://{}", addr), None, &options)
+            .await
+            .unwrap_err();
+        assert!(err.is_timeout());
+        assert_eq!(err.phase(), WsPhase::Upgrade);
+
+        let url = format!("wss://localhost:{}", addr.port());
+        let err = connect_ws_with_options(url, Some(&addr.to_string()), &options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.phase(), WsPhase::Tls);
+
+        let err = connect_ws_with_options("ws://a.com", Some("bad"), &options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.phase(), WsPhase::Request);
+        assert!(!err.is_timeout());
+    }
+
+    async fn ws_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    while ws.next().await.is_some() {}
+                });
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn race_first_handshake_wins() {
+        let silent = silent_server().await;
+        let up = ws_server().await;
+        let options = WsConnectOptions::default().timeout(Duration::from_secs(2));
+        let url = format!("ws://localhost:{}", up.port());
+
+        let addrs = [silent.to_string(), "127.0.0.1".to_string()];
+        let raced = connect_ws_race(&url, &addrs, Duration::from_millis(20), &options)
+            .await
+            .unwrap();
+        assert_eq!(raced.addr, up.to_string());
+        assert!(raced.latency < Duration::from_secs(1));
+
+        let options = options.timeout(Duration::from_millis(100));
+        let err = connect_ws_race(&url, &[silent.to_string()], Duration::ZERO, &options)
+            .await
+            .unwrap_err();
+        assert_eq!(err.phase(), WsPhase::Upgrade);
+        let empty: [&str; 0] = [];
+        assert!(connect_ws_race(&url, &empty, Duration::ZERO, &options)
+            .await
+            .is_err());
+    }
+
+    // 回调的返回类型由 tungstenite 决定
+    #[allow(clippy::result_large_err)]
+    #[tokio::test]
+    async fn headers_and_subprotocol() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let callback = |req: &ServerRequest, mut resp: ServerResponse| {
+                assert_eq!(req.headers()["x-api-key"], "k1");
+                assert_eq!(req.headers()["sec-websocket-protocol"], "v1, v2");
+                resp.headers_mut()
+                    .insert(SEC_WEBSOCKET_PROTOCOL, HeaderValue::from_static("v2"));
+                Ok(resp)
+            };
+            let _ws = tokio_tungstenite::accept_hdr_async(tcp, callback)
+                .await
+                .unwrap();
+        });
+
+        let options = WsConnectOptions::default()
+            .header("X-Api-Key", "k1")
+            .subprotocol("v1")
+            .subprotocol("v2");
+        let (_, resp) = connect_ws_with_options(format!("ws://{}", addr), None, &options)
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[SEC_WEBSOCKET_PROTOCOL], "v2");
+    }
+}
@@ -1,6 +1,8 @@
 use anyhow::{anyhow, Result};
 use std::time::Duration;
 
+use super::req::{AddrOrder, HostsResolver};
+
 ///加载本地hosts文件
 async fn load_file_host(path: &str) -> Result<String> {
     tokio::fs::read_to_string(path)
@@ -16,6 +18,12 @@ pub async fn load_host_file(path: &str) -> Result<String> {
     }
 }
 
+/// 加载 hosts 文件并生成域名解析，用于 RequestSpec::resolver
+pub async fn load_host_resolver(path: &str, order: AddrOrder) -> Result<HostsResolver> {
+    let txt = load_host_file(path).await?;
+    Ok(HostsResolver::from_hosts_text(&txt, order))
+}
+
 pub fn split_txt(txt: impl AsRef<str>) -> Vec<String> {
     let d = txt.as_ref();
 
@@ -36,5 +44,39 @@ pub fn split_txt(txt: impl AsRef<str>) -> Vec<String> {
 
 ///加载远程地址的hosts文件
 async fn load_url_host(url: &str) -> Result<String> {
-    super::req::get_with_timeout(url, None, &None, Duration::from_millis(30000)).await
+    Ok(super::req::get_with_timeout(url, None, &None, Duration::from_millis(30000)).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::tool::req::{with_transport, MockTransport};
+
+    #[tokio::test]
+    async fn load_from_url_and_file() {
+        let url = "http://mock.invalid/hosts";
+        let mock = Arc::new(MockTransport::new().get(url, "1.1.1.1 a.com"));
+        let txt = with_transport(mock.clone(), load_host_file(url))
+            .await
+            .unwrap();
+        assert_eq!(txt, "1.1.1.1 a.com");
+        assert_eq!(mock.hits(url), 1);
+
+        let path = std::env::temp_dir().join(format!("hosts_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        tokio::fs::write(path, "1.1.1.2 b.com").await.unwrap();
+        let r = load_host_resolver(path, AddrOrder::RoundRobin)
+            .await
+            .unwrap();
+        tokio::fs::remove_file(path).await.unwrap();
+        assert_eq!(
+            r.lookup("b.com"),
+            vec!["1.1.1.2".parse::<std::net::IpAddr>().unwrap()]
+        );
+
+        let mock = Arc::new(MockTransport::new());
+        assert!(with_transport(mock, load_host_file(url)).await.is_err());
+    }
 }
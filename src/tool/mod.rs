@@ -7,9 +7,11 @@ pub mod aes;
 pub mod file;
 pub mod hosts;
 pub mod libtime;
+pub mod probe;
 pub mod random;
 pub mod remove_list;
 pub mod req;
+pub mod tls;
 pub mod typ;
 
 pub mod config;
@@ -50,3 +52,19 @@ where
         .map_err(|err| D::Error::custom(err.to_string()))?
         .into())
 }
+
+/// 可选的时间配置，例如 "30s"，不填时为 None
+pub fn deserialize_opt_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    let value: Option<String> = Deserialize::deserialize(deserializer)?;
+    value
+        .map(|v| {
+            humantime::Duration::from_str(&v)
+                .map(Into::into)
+                .map_err(|err| D::Error::custom(err.to_string()))
+        })
+        .transpose()
+}
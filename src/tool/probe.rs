@@ -0,0 +1,341 @@
+//! 延迟探测：对同一域名的多个地址测量 tcp 连接 / tls 握手 / http 请求耗时，
+//! 保留最近若干次结果，选出最快的地址
+//!
+//! ```ignore
+//! let txt = hosts::load_host_file("./hosts").await?;
+//! let prober = Arc::new(Prober::new(ProbeKind::Tls));
+//! prober.load_hosts_text(&txt, 443);
+//! let task = prober.spawn(Duration::from_secs(30));
+//!
+//! // http 请求固定解析到最快的地址
+//! RequestSpec::get(url).fastest(&prober).send().await?;
+//! // websocket 连接到最快的地址
+//! let addr = prober.best_addr("ws.example.com").unwrap();
+//! connect_ws_with_addr(ws_url, addr.to_string()).await?;
+//! // 停止后台探测
+//! task.stop();
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use futures_util::future::join_all;
+use parking_lot::{Mutex, RwLock};
+use reqwest::Client;
+use rustls::ServerName;
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsConnector;
+
+use super::req::{try_build_client, AddrOrder, ClientKey, ClientOptions, HostsResolver};
+use super::tls::default_tls_options;
+
+/// 每个地址默认保留的结果数量
+pub const DEFAULT_WINDOW: usize = 20;
+
+/// 探测方式
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProbeKind {
+    /// tcp 连接耗时
+    Tcp,
+    /// tcp 连接 + tls 握手耗时
+    Tls,
+    /// 完整的 http 请求耗时，参数为请求的 url，url 中的域名会解析到被测地址
+    Http(String),
+}
+
+/// 单个地址的最近探测结果
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    /// 最近成功的耗时，最多保留 window 个
+    pub samples: VecDeque<Duration>,
+    pub success: u64,
+    pub failures: u64,
+    /// 最近一次是否失败
+    pub last_failed: bool,
+}
+
+impl LatencyStats {
+    fn record(&mut self, rs: Option<Duration>, window: usize) {
+        match rs {
+            Some(d) => {
+                if self.samples.len() >= window {
+                    self.samples.pop_front();
+                }
+                self.samples.push_back(d);
+                self.success += 1;
+                self.last_failed = false;
+            }
+            None => {
+                self.failures += 1;
+                self.last_failed = true;
+            }
+        }
+    }
+
+    pub fn avg(&self) -> Option<Duration> {
+        let n = self.samples.len() as u32;
+        (n > 0).then(|| self.samples.iter().sum::<Duration>() / n)
+    }
+
+    pub fn min(&self) -> Option<Duration> {
+        self.samples.iter().min().copied()
+    }
+
+    pub fn last(&self) -> Option<Duration> {
+        self.samples.back().copied()
+    }
+
+    /// 用于排序的分数，最近一次失败或没有结果时为 None
+    pub fn score(&self) -> Option<Duration> {
+        match self.last_failed {
+            true => None,
+            false => self.avg(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Prober {
+    kind: ProbeKind,
+    timeout: Duration,
+    window: usize,
+    targets: RwLock<HashMap<String, Vec<SocketAddr>>>,
+    stats: Mutex<HashMap<SocketAddr, LatencyStats>>,
+    // Http 探测使用的 Client，每个地址一个，不经过全局缓存和中间件
+    clients: Mutex<HashMap<SocketAddr, Client>>,
+}
+
+impl Prober {
+    pub fn new(kind: ProbeKind) -> Self {
+        Self {
+            kind,
+            timeout: Duration::from_secs(3),
+            window: DEFAULT_WINDOW,
+            targets: RwLock::new(HashMap::new()),
+            stats: Mutex::new(HashMap::new()),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 单次探测的超时时间，超时记为失败
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn window(mut self, window: usize) -> Self {
+        self.window = window.max(1);
+        self
+    }
+
+    pub fn add_target(&self, host: &str, addrs: impl IntoIterator<Item = SocketAddr>) {
+        let mut targets = self.targets.write();
+        let list = targets.entry(host.to_ascii_lowercase()).or_default();
+        for a in addrs {
+            if !list.contains(&a) {
+                list.push(a);
+            }
+        }
+    }
+
+    /// 加载 hosts 文件内容，所有地址使用同一个端口
+    pub fn load_hosts_text(&self, text: &str, port: u16) {
+        self.load_resolver(
+            &HostsResolver::from_hosts_text(text, AddrOrder::RoundRobin),
+            port,
+        );
+    }
+
+    /// 探测 resolver 中配置的所有地址
+    pub fn load_resolver(&self, resolver: &HostsResolver, port: u16) {
+        for host in resolver.hosts() {
+            let ips = resolver.lookup(&host);
+            self.add_target(&host, ips.into_iter().map(|ip| SocketAddr::new(ip, port)));
+        }
+    }
+
+    pub fn stats(&self, addr: &SocketAddr) -> Option<LatencyStats> {
+        self.stats.lock().get(addr).cloned()
+    }
+
+    /// 域名的所有地址，按平均耗时从快到慢，失败或未探测的排在最后
+    pub fn ranked(&self, host: &str) -> Vec<(SocketAddr, Option<Duration>)> {
+        let addrs = self
+            .targets
+            .read()
+            .get(&host.to_ascii_lowercase())
+            .cloned()
+            .unwrap_or_default();
+        let stats = self.stats.lock();
+        let mut ranked = addrs
+            .into_iter()
+            .map(|a| (a, stats.get(&a).and_then(LatencyStats::score)))
+            .collect::<Vec<_>>();
+        ranked.sort_by_key(|(_, d)| d.unwrap_or(Duration::MAX));
+        ranked
+    }
+
+    /// 当前最快的地址，所有地址都不可用时返回 None
+    pub fn best_addr(&self, host: &str) -> Option<SocketAddr> {
+        self.ranked(host)
+            .into_iter()
+            .find(|(_, d)| d.is_some())
+            .map(|(a, _)| a)
+    }
+
+    /// 把探测结果同步到 resolver，配合 AddrOrder::FastestFirst 使用
+    pub fn feed(&self, resolver: &HostsResolver) {
+        let mut best = HashMap::<IpAddr, Duration>::new();
+        for (addr, s) in self.stats.lock().iter() {
+            let d = s.score().unwrap_or(Duration::MAX);
+            let e = best.entry(addr.ip()).or_insert(d);
+            *e = (*e).min(d);
+        }
+        for (ip, d) in best {
+            resolver.report_latency(ip, d);
+        }
+    }
+
+    /// 探测一遍所有地址
+    pub async fn probe_all(&self) {
+        let targets = self
+            .targets
+            .read()
+            .iter()
+            .flat_map(|(h, addrs)| addrs.iter().map(move |a| (h.clone(), *a)))
+            .collect::<Vec<_>>();
+        let results = join_all(targets.iter().map(|(h, a)| self.probe(h, *a))).await;
+
+        let mut stats = self.stats.lock();
+        for ((_, addr), rs) in targets.into_iter().zip(results) {
+            stats.entry(addr).or_default().record(rs.ok(), self.window);
+        }
+    }
+
+    /// 探测单个地址，返回耗时
+    pub async fn probe(&self, host: &str, addr: SocketAddr) -> Result<Duration> {
+        let start = Instant::now();
+        tokio::time::timeout(self.timeout, self.probe_inner(host, addr))
+            .await
+            .map_err(|_| anyhow!("探测 {} 超时", addr))??;
+        Ok(start.elapsed())
+    }
+
+    async fn probe_inner(&self, host: &str, addr: SocketAddr) -> Result<()> {
+        match &self.kind {
+            ProbeKind::Tcp => {
+                TcpStream::connect(addr).await?;
+            }
+            ProbeKind::Tls => {
+                let tcp = TcpStream::connect(addr).await?;
+                let config = default_tls_options().client_config()?;
+                let name = ServerName::try_from(host)?;
+                TlsConnector::from(Arc::new(config))
+                    .connect(name, tcp)
+                    .await?;
+            }
+            ProbeKind::Http(url) => {
+                self.http_client(url, addr)?.get(url).send().await?;
+            }
+        }
+        Ok(())
+    }
+
+    // 固定解析到 addr 的 Client；不保留空闲连接，每次探测都重新建立连接
+    fn http_client(&self, url: &str, addr: SocketAddr) -> Result<Client> {
+        if let Some(cli) = self.clients.lock().get(&addr) {
+            return Ok(cli.clone());
+        }
+        let options = ClientOptions {
+            pool_max_idle_per_host: Some(0),
+            ..Default::default()
+        };
+        let key = ClientKey::new(&None, url, Some(&addr.to_string()), self.timeout)?
+            .with_options(options);
+        let cli = try_build_client(&key)?;
+        self.clients.lock().insert(addr, cli.clone());
+        Ok(cli)
+    }
+
+    /// 后台定期探测，调用 [`ProbeTask::stop`] 或 Prober 被释放后停止
+    pub fn spawn(self: &Arc<Self>, interval: Duration) -> ProbeTask {
+        let prober = Arc::downgrade(self);
+        ProbeTask(tokio::spawn(async move {
+            while let Some(p) = Weak::upgrade(&prober) {
+                p.probe_all().await;
+                drop(p);
+                tokio::time::sleep(interval).await;
+            }
+        }))
+    }
+}
+
+/// 后台探测任务，释放时不会停止
+#[derive(Debug)]
+pub struct ProbeTask(pub(crate) JoinHandle<()>);
+
+impl ProbeTask {
+    pub fn stop(&self) {
+        self.0.abort();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.is_finished()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn rolling_stats() {
+        let mut s = LatencyStats::default();
+        for ms in [10, 20, 30] {
+            s.record(Some(Duration::from_millis(ms)), 2);
+        }
+        assert_eq!(s.avg(), Some(Duration::from_millis(25)));
+        s.record(None, 2);
+        assert_eq!(s.score(), None);
+        assert_eq!((s.success, s.failures), (3, 1));
+    }
+
+    #[tokio::test]
+    async fn best_addr() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let up = listener.local_addr().unwrap();
+        // 绑定后立即关闭，得到一个没有监听的端口
+        let down = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+
+        let p = Prober::new(ProbeKind::Tcp).timeout(Duration::from_secs(1));
+        p.add_target("a.com", [down, up]);
+        assert_eq!(p.best_addr("a.com"), None);
+        p.probe_all().await;
+        assert_eq!(p.best_addr("A.com"), Some(up));
+        assert!(p.stats(&down).unwrap().last_failed);
+    }
+
+    #[tokio::test]
+    async fn stop_background_probe() {
+        let p = Arc::new(Prober::new(ProbeKind::Tcp));
+        let task = p.spawn(Duration::from_millis(10));
+        task.stop();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(task.is_finished());
+
+        // Prober 释放后任务自动结束
+        let task = p.spawn(Duration::from_millis(10));
+        drop(p);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(task.is_finished());
+    }
+}
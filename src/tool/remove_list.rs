@@ -2,6 +2,7 @@ use std::collections::{HashMap, HashSet};
 use std::time::Duration;
 
 use crate::tool::blacklist_detach;
+use crate::tool::req::{ReqError, RetryPolicy};
 use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
 use tracing::error;
@@ -111,30 +112,31 @@ pub async fn load_all_list(
     Ok((blacklist, whitelist))
 }
 
-//retry 重试次数
+//retry 重试次数，为 0 时不请求
 pub async fn merge(url: &str, data: &mut HashSet<String>, retry: u8) -> Result<()> {
-    let mut er = Ok(());
-
-    for _ in 0..retry {
-        let text =
-            match super::req::get_with_timeout(url, None, &None, Duration::from_secs(10)).await {
-                Ok(s) => s,
-                Err(err) => {
-                    er = Err(err);
-                    continue;
-                }
-            };
-
-        if text.is_empty() {
-            er = Err(anyhow!("响应数据为空"));
-            continue;
-        }
-
-        hash_set_merge(data, blacklist_detach(&text));
+    if retry == 0 {
         return Ok(());
     }
+    let text = list_retry_policy(retry).run(|| load_text(url)).await?;
+    hash_set_merge(data, blacklist_detach(&text));
+    Ok(())
+}
+
+// 列表加载的重试策略：任何错误（包括 404 等状态码、空响应）都重试，重试前按退避时间等待
+fn list_retry_policy(retry: u8) -> RetryPolicy {
+    RetryPolicy::new(retry as u32).retry_all(true)
+}
 
-    er
+// 加载远程文本，空响应视为错误
+async fn load_text(url: &str) -> Result<String, ReqError> {
+    let text = super::req::get_with_timeout(url, None, &None, Duration::from_secs(10)).await?;
+    if text.is_empty() {
+        return Err(ReqError::Decode {
+            url: url.to_string(),
+            msg: "响应数据为空".to_string(),
+        });
+    }
+    Ok(text)
 }
 
 //retry 重试次数
@@ -147,39 +149,23 @@ pub async fn force_price_pre_load<T>(
 where
     T: for<'de> Deserialize<'de>,
 {
-    let mut er = Err(anyhow!("加载失败"));
-
-    for _ in 0..retry {
-        let text =
-            match super::req::get_with_timeout(url, None, &None, Duration::from_secs(10)).await {
-                Ok(s) => s,
-                Err(err) => {
-                    er = Err(err);
-                    continue;
-                }
-            };
-
-        if text.is_empty() {
-            er = Err(anyhow!("响应数据为空"));
-            continue;
-        }
-
-        let d = str_to_t(&text)?;
+    if retry == 0 {
+        return Err(anyhow!("加载失败"));
+    }
+    let text = list_retry_policy(retry).run(|| load_text(url)).await?;
+    let d = str_to_t(&text)?;
 
-        if let Some(file_save_path) = file_save_path {
-            // 先删除文件，避免有其他写入意外
-            if let Err(err) = tokio::fs::remove_file(file_save_path).await {
-                error!("删除 {} 文件失败：{err}", file_save_path);
-            }
-            if let Err(err) = tokio::fs::write(file_save_path, text).await {
-                error!("写入 {} 文件失败：{err}", file_save_path);
-            }
+    if let Some(file_save_path) = file_save_path {
+        // 先删除文件，避免有其他写入意外
+        if let Err(err) = tokio::fs::remove_file(file_save_path).await {
+            error!("删除 {} 文件失败：{err}", file_save_path);
+        }
+        if let Err(err) = tokio::fs::write(file_save_path, text).await {
+            error!("写入 {} 文件失败：{err}", file_save_path);
         }
-
-        return Ok(d);
     }
 
-    er
+    Ok(d)
 }
 
 pub fn str_to_t<T>(text: &str) -> Result<T>
@@ -193,3 +179,83 @@ where
 
     Ok(d)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::tool::req::{with_transport, MockReply, MockTransport};
+
+    const BLACK: &str = "http://mock.invalid/black";
+
+    fn down() -> ReqError {
+        ReqError::Connect {
+            url: BLACK.to_string(),
+            msg: "down".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn merge_with_retry() {
+        let mock = Arc::new(
+            MockTransport::new()
+                .get(BLACK, "\"a\", b")
+                .fail_times(BLACK, 1, down())
+                .script(BLACK, [MockReply::ok("")]),
+        );
+        let mut data = HashSet::from(["c".to_string()]);
+        with_transport(mock.clone(), merge(BLACK, &mut data, 3))
+            .await
+            .unwrap();
+        // 连接失败和空响应都会重试
+        assert_eq!(mock.hits(BLACK), 3);
+        assert_eq!(data.len(), 3);
+        assert!(data.contains("a") && data.contains("b"));
+
+        let mock = Arc::new(MockTransport::new().fail_times(BLACK, 2, down()));
+        let rs = with_transport(mock.clone(), merge(BLACK, &mut data, 2)).await;
+        assert!(rs.is_err());
+        assert_eq!(mock.hits(BLACK), 2);
+
+        // 404 也会重试
+        let mock = Arc::new(MockTransport::new());
+        let rs = with_transport(mock.clone(), merge(BLACK, &mut data, 2)).await;
+        assert!(rs.is_err());
+        assert_eq!(mock.hits(BLACK), 2);
+
+        // 次数为 0 时不请求
+        let mock = Arc::new(MockTransport::new());
+        with_transport(mock.clone(), merge(BLACK, &mut data, 0))
+            .await
+            .unwrap();
+        assert_eq!(mock.hits(BLACK), 0);
+    }
+
+    #[tokio::test]
+    async fn force_price_pre() {
+        let url = "http://mock.invalid/pre";
+        let json = r#"{"defaultMaxPre": 0.5, "ops": {"r1": 1.5}}"#;
+        let mock = Arc::new(MockTransport::new().get(url, json));
+        let path = std::env::temp_dir().join(format!("force_pre_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+
+        let pre: RemoveForcePricePre =
+            with_transport(mock, force_price_pre_load(url, 1, Some(path)))
+                .await
+                .unwrap();
+        assert_eq!(pre.default_max_pre, 0.5);
+        assert_eq!(pre.ops["r1"], 1.5);
+        assert_eq!(tokio::fs::read_to_string(path).await.unwrap(), json);
+        tokio::fs::remove_file(path).await.unwrap();
+
+        // 内容无法解析时返回错误
+        let mock = Arc::new(MockTransport::new().get(url, "[1"));
+        let rs = with_transport(
+            mock,
+            force_price_pre_load::<RemoveForcePricePre>(url, 1, None),
+        )
+        .await;
+        assert!(rs.is_err());
+    }
+}
@@ -0,0 +1,493 @@
+//! 响应缓存：TTL 内直接使用缓存，过期后用 ETag / Last-Modified 条件请求，
+//! 上游出错时可以退回旧数据
+//!
+//! ```ignore
+//! let cache = Arc::new(
+//!     ResponseCache::new(Duration::from_secs(5))
+//!         .stale_if_error(Duration::from_secs(3600))
+//!         .disk("./cache"),
+//! );
+//! let text = RequestSpec::get(url).cache(cache.clone()).send().await?.into_text();
+//! ```
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use data_encoding::{BASE64, HEXLOWER};
+use parking_lot::Mutex;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+    LAST_MODIFIED,
+};
+use reqwest::{Method, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, warn};
+
+use super::{is_sensitive_header, ReqError, ReqResponse, RequestSpec, SuccessPolicy};
+use crate::tool::libtime;
+
+/// 内存中默认最多缓存的响应数量
+pub const DEFAULT_RESPONSE_CACHE_CAPACITY: usize = 1024;
+
+/// 缓存的响应
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub url: String,
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    /// base64 编码
+    pub body: String,
+    /// 写入或最近一次验证的时间，ms
+    pub stored_at: i64,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl CacheEntry {
+    fn from_response(resp: &ReqResponse) -> Self {
+        let header = |name| {
+            resp.headers
+                .get(name)
+                .and_then(|v: &HeaderValue| v.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            url: resp.url.clone(),
+            status: resp.status.as_u16(),
+            headers: resp
+                .headers
+                .iter()
+                .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+                .collect(),
+            body: BASE64.encode(&resp.body),
+            stored_at: libtime::get_now_millis(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    pub fn age(&self) -> Duration {
+        let ms = libtime::get_now_millis().saturating_sub(self.stored_at);
+        Duration::from_millis(ms.max(0) as u64)
+    }
+
+    pub fn to_response(&self) -> ReqResponse {
+        let mut headers = HeaderMap::new();
+        for (k, v) in self.headers.iter() {
+            if let (Ok(k), Ok(v)) = (
+                HeaderName::from_bytes(k.as_bytes()),
+                HeaderValue::from_str(v),
+            ) {
+                headers.append(k, v);
+            }
+        }
+        ReqResponse {
+            url: self.url.clone(),
+            status: StatusCode::from_u16(self.status).unwrap_or(StatusCode::OK),
+            headers,
+            body: BASE64.decode(self.body.as_bytes()).unwrap_or_default(),
+        }
+    }
+}
+
+/// 按最近使用时间淘汰的内存缓存
+#[derive(Debug)]
+struct Entries {
+    capacity: usize,
+    tick: u64,
+    map: HashMap<String, (CacheEntry, u64)>,
+}
+
+impl Entries {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            tick: 0,
+            map: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<CacheEntry> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.map.get_mut(key).map(|(e, used)| {
+            *used = tick;
+            e.clone()
+        })
+    }
+
+    fn insert(&mut self, key: &str, entry: CacheEntry) {
+        self.tick += 1;
+        if !self.map.contains_key(key) {
+            self.shrink_to(self.capacity - 1);
+        }
+        self.map.insert(key.to_string(), (entry, self.tick));
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.map.remove(key);
+    }
+
+    // 淘汰最久未使用的，直到数量不超过 len
+    fn shrink_to(&mut self, len: usize) {
+        while self.map.len() > len {
+            let oldest = self
+                .map
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(k) => self.map.remove(&k),
+                None => break,
+            };
+        }
+    }
+}
+
+/// 响应缓存，只缓存 GET 请求的 2xx 响应
+///
+/// 响应带 `Cache-Control: no-store` / `private` 时不缓存；
+/// 设置了 signer 或带有鉴权请求头（未加入 vary）的请求不使用缓存。
+/// 中间件添加的请求头不参与缓存 key
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    /// 参与缓存 key 的请求头，小写
+    vary: Vec<String>,
+    /// 请求失败时，过期多久以内的缓存仍可使用
+    stale_if_error: Option<Duration>,
+    dir: Option<PathBuf>,
+    entries: Mutex<Entries>,
+}
+
+impl ResponseCache {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            vary: Vec::new(),
+            stale_if_error: None,
+            dir: None,
+            entries: Mutex::new(Entries::new(DEFAULT_RESPONSE_CACHE_CAPACITY)),
+        }
+    }
+
+    /// 内存中最多缓存的响应数量，超出时淘汰最久未使用的，磁盘文件不受影响
+    pub fn capacity(self, capacity: usize) -> Self {
+        {
+            let mut entries = self.entries.lock();
+            entries.capacity = capacity.max(1);
+            let cap = entries.capacity;
+            entries.shrink_to(cap);
+        }
+        self
+    }
+
+    /// 不同请求头值分开缓存；加入 vary 的鉴权请求头（例如 Authorization）可以使用缓存
+    pub fn vary(mut self, header: impl Into<String>) -> Self {
+        self.vary.push(header.into().to_ascii_lowercase());
+        self
+    }
+
+    /// 请求失败（连接失败、超时、非成功状态码等）时，使用过期不超过 max_stale 的缓存
+    pub fn stale_if_error(mut self, max_stale: Duration) -> Self {
+        self.stale_if_error = Some(max_stale);
+        self
+    }
+
+    /// 同时保存到目录下，重启后仍可使用
+    pub fn disk(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = Some(dir.into());
+        self
+    }
+
+    /// 缓存 key：method + 完整 url + vary 请求头
+    pub fn key(&self, method: &Method, url: &str, headers: &[(String, String)]) -> String {
+        let mut key = format!("{} {}", method, url);
+        for name in self.vary.iter() {
+            let v = headers
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+                .unwrap_or_default();
+            key.push_str(&format!("\n{}: {}", name, v));
+        }
+        key
+    }
+
+    /// 查找缓存，内存中没有时从磁盘加载
+    pub async fn get(&self, key: &str) -> Option<CacheEntry> {
+        if let Some(e) = self.entries.lock().get(key) {
+            return Some(e);
+        }
+        let path = self.path_of(key)?;
+        let data = tokio::fs::read(&path).await.ok()?;
+        let entry = serde_json::from_slice::<CacheEntry>(&data).ok()?;
+        self.entries.lock().insert(key, entry.clone());
+        Some(entry)
+    }
+
+    pub async fn put(&self, key: &str, entry: CacheEntry) {
+        if let Some(path) = self.path_of(key) {
+            let rs = match serde_json::to_vec(&entry) {
+                Ok(data) => write_file(&path, &data).await,
+                Err(err) => Err(err.to_string()),
+            };
+            if let Err(err) = rs {
+                warn!("写入缓存文件 {} 失败：{}", path.display(), err);
+            }
+        }
+        self.entries.lock().insert(key, entry);
+    }
+
+    pub async fn remove(&self, key: &str) {
+        self.entries.lock().remove(key);
+        if let Some(path) = self.path_of(key) {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+
+    /// 清空内存中的缓存，磁盘文件保留
+    pub fn clear(&self) {
+        self.entries.lock().map.clear();
+    }
+
+    /// 内存中缓存的响应数量
+    pub fn len(&self) -> usize {
+        self.entries.lock().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn path_of(&self, key: &str) -> Option<PathBuf> {
+        let dir = self.dir.as_ref()?;
+        let name = HEXLOWER.encode(&Sha256::digest(key.as_bytes()));
+        Some(dir.join(format!("{}.json", name)))
+    }
+
+    /// 带缓存发送请求
+    pub(crate) async fn fetch(&self, mut spec: RequestSpec) -> Result<ReqResponse, ReqError> {
+        if spec.method != Method::GET || !self.cacheable_request(&spec) {
+            return spec.send_uncached().await;
+        }
+        let key = self.key(&spec.method, &spec.full_url()?, &spec.headers);
+        let cached = self.get(&key).await;
+
+        if let Some(e) = &cached {
+            if e.age() < self.ttl {
+                debug!("使用缓存：{}", e.url);
+                return Ok(e.to_response());
+            }
+            if let Some(etag) = &e.etag {
+                spec = spec.header(IF_NONE_MATCH.as_str(), etag.as_str());
+            }
+            if let Some(lm) = &e.last_modified {
+                spec = spec.header(IF_MODIFIED_SINCE.as_str(), lm.as_str());
+            }
+            // 304 也算成功，之后使用缓存内容
+            let success = spec.success.clone();
+            spec.success = SuccessPolicy::custom(move |s| {
+                s == StatusCode::NOT_MODIFIED || success.is_success(s)
+            });
+        }
+
+        match (spec.send_uncached().await, cached) {
+            (Ok(resp), Some(mut e)) if resp.status == StatusCode::NOT_MODIFIED => {
+                debug!("缓存未变化：{}", e.url);
+                e.stored_at = libtime::get_now_millis();
+                let resp = e.to_response();
+                self.put(&key, e).await;
+                Ok(resp)
+            }
+            (Ok(resp), _) if cacheable_response(&resp) => {
+                self.put(&key, CacheEntry::from_response(&resp)).await;
+                Ok(resp)
+            }
+            (Ok(resp), cached) => {
+                if cached.is_some() {
+                    self.remove(&key).await;
+                }
+                Ok(resp)
+            }
+            (Err(err), Some(e)) if self.usable_stale(&e, &err) => {
+                warn!("请求失败，使用过期缓存：{} 错误：{}", e.url, err);
+                Ok(e.to_response())
+            }
+            (Err(err), _) => Err(err),
+        }
+    }
+
+    // 签名的请求、带鉴权请求头（未加入 vary）的请求不缓存，避免不同账号共用缓存
+    fn cacheable_request(&self, spec: &RequestSpec) -> bool {
+        spec.signer.is_none()
+            && spec.headers.iter().all(|(k, _)| {
+                !is_sensitive_header(k) || self.vary.contains(&k.to_ascii_lowercase())
+            })
+    }
+
+    fn usable_stale(&self, e: &CacheEntry, err: &ReqError) -> bool {
+        match (self.stale_if_error, err) {
+            (_, ReqError::Build { .. }) | (None, _) => false,
+            (Some(max_stale), _) => e.age() < self.ttl + max_stale,
+        }
+    }
+}
+
+// 只缓存 2xx，且服务端未禁止缓存
+fn cacheable_response(resp: &ReqResponse) -> bool {
+    if !resp.status.is_success() {
+        return false;
+    }
+    !resp.headers.get_all(CACHE_CONTROL).iter().any(|v| {
+        v.to_str().unwrap_or_default().split(',').any(|d| {
+            let d = d.trim();
+            d.eq_ignore_ascii_case("no-store") || d.eq_ignore_ascii_case("private")
+        })
+    })
+}
+
+static TMP_SEQ: AtomicU64 = AtomicU64::new(0);
+
+// 先写临时文件再改名，避免读到写了一半的文件；临时文件名唯一，并发写入同一个 key 时互不影响
+async fn write_file(path: &std::path::Path, data: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .map_err(|err| err.to_string())?;
+    }
+    let seq = TMP_SEQ.fetch_add(1, Ordering::Relaxed);
+    let tmp = path.with_extension(format!("{}.{}.tmp", std::process::id(), seq));
+    tokio::fs::write(&tmp, data)
+        .await
+        .map_err(|err| err.to_string())?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use reqwest::Response;
+
+    use super::*;
+    use crate::tool::req::{Middleware, ReqContext};
+
+    // 模拟上游：status 为 0 时按 If-None-Match 返回 304 或 200
+    #[derive(Default)]
+    struct Upstream {
+        hits: AtomicUsize,
+        status: AtomicU16,
+    }
+
+    impl Middleware for Arc<Upstream> {
+        fn before_send(&self, ctx: &mut ReqContext) -> Result<Option<Response>, ReqError> {
+            self.hits.fetch_add(1, Ordering::SeqCst);
+            let status = match self.status.load(Ordering::SeqCst) {
+                0 if ctx
+                    .headers
+                    .iter()
+                    .any(|(k, v)| k == "if-none-match" && v == "v1") =>
+                {
+                    304
+                }
+                0 => 200,
+                s => s,
+            };
+            let resp = http::Response::builder()
+                .status(status)
+                .header(ETAG, "v1")
+                .body(if status == 200 { "list" } else { "" })
+                .unwrap();
+            Ok(Some(Response::from(resp)))
+        }
+    }
+
+    async fn fetch(cache: &Arc<ResponseCache>, up: &Arc<Upstream>) -> Result<String, ReqError> {
+        RequestSpec::get("http://mock.invalid/list")
+            .middleware(up.clone())
+            .cache(cache.clone())
+            .send()
+            .await
+            .map(ReqResponse::into_text)
+    }
+
+    #[tokio::test]
+    async fn ttl_and_revalidate() {
+        let up = Arc::new(Upstream::default());
+        let cache = Arc::new(ResponseCache::new(Duration::from_secs(60)));
+        assert_eq!(fetch(&cache, &up).await.unwrap(), "list");
+        assert_eq!(fetch(&cache, &up).await.unwrap(), "list");
+        assert_eq!(up.hits.load(Ordering::SeqCst), 1);
+
+        // 过期后条件请求，返回 304 时使用缓存内容
+        let cache = Arc::new(ResponseCache::new(Duration::ZERO));
+        assert_eq!(fetch(&cache, &up).await.unwrap(), "list");
+        assert_eq!(fetch(&cache, &up).await.unwrap(), "list");
+        assert_eq!(up.hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn stale_if_error_from_disk() {
+        let dir = std::env::temp_dir().join(format!("req_cache_{}", libtime::get_now_millis()));
+        let up = Arc::new(Upstream::default());
+        let cache = Arc::new(ResponseCache::new(Duration::ZERO).disk(&dir));
+        assert_eq!(fetch(&cache, &up).await.unwrap(), "list");
+
+        up.status.store(503, Ordering::SeqCst);
+        assert!(fetch(&cache, &up).await.is_err());
+
+        // 新实例从磁盘加载
+        let cache = Arc::new(
+            ResponseCache::new(Duration::ZERO)
+                .stale_if_error(Duration::from_secs(60))
+                .disk(&dir),
+        );
+        assert_eq!(fetch(&cache, &up).await.unwrap(), "list");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn only_cacheable_responses() {
+        use crate::tool::req::{MockReply, MockTransport};
+
+        const URL: &str = "http://mock.invalid/c";
+        let mock = Arc::new(MockTransport::new().get(URL, "ok").script(
+            URL,
+            [
+                MockReply::ok("a").header("cache-control", "private, max-age=60"),
+                MockReply::status(StatusCode::ACCEPTED, "b"),
+            ],
+        ));
+        let cache = Arc::new(ResponseCache::new(Duration::from_secs(60)).capacity(1));
+        let get = |spec: RequestSpec| {
+            let cache = cache.clone();
+            let mock = mock.clone();
+            async move { spec.transport(mock).cache(cache).send().await.unwrap() }
+        };
+
+        // private 不缓存，202 缓存
+        assert_eq!(get(RequestSpec::get(URL)).await.text(), "a");
+        assert_eq!(get(RequestSpec::get(URL)).await.text(), "b");
+        assert_eq!(get(RequestSpec::get(URL)).await.text(), "b");
+        assert_eq!(mock.hits(URL), 2);
+
+        // 带鉴权头的请求不使用缓存
+        let signed = || RequestSpec::get(URL).header("X-MBX-APIKEY", "k");
+        assert_eq!(get(signed()).await.text(), "ok");
+        assert_eq!(get(signed()).await.text(), "ok");
+        assert_eq!(mock.hits(URL), 4);
+
+        // 超出容量时淘汰最久未使用的
+        get(RequestSpec::get(URL).query("p", "1")).await;
+        assert_eq!(cache.len(), 1);
+        assert_eq!(get(RequestSpec::get(URL)).await.text(), "ok");
+        assert_eq!(mock.hits(URL), 6);
+    }
+}
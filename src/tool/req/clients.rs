@@ -0,0 +1,287 @@
+//! 缓存已创建的 Client，按完整配置区分
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use reqwest::{Client, Url};
+
+use crate::tool::tls::{default_tls_options, TlsOptions};
+
+use super::{
+    global_middlewares, try_build_client, ClientOptions, HostsResolver, MiddlewareStack, ReqError,
+    ResolverRef,
+};
+
+/// 默认最多缓存的 Client 数量
+pub const DEFAULT_CLIENT_CACHE_CAPACITY: usize = 64;
+
+static CLI: Lazy<Mutex<ClientCache>> =
+    Lazy::new(|| Mutex::new(ClientCache::new(DEFAULT_CLIENT_CACHE_CAPACITY)));
+
+/// Client 的缓存 key，包含所有会影响 Client 行为的配置
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientKey {
+    pub proxy: Option<String>,
+    /// 域名固定解析：(host, ip:port)
+    pub resolve: Option<(String, String)>,
+    /// 自定义域名解析
+    pub resolver: Option<ResolverRef>,
+    pub timeout: Duration,
+    pub tls: TlsOptions,
+    pub options: ClientOptions,
+}
+
+impl ClientKey {
+    /// 指定 dns 时从 url 中取域名，url 不合法时返回错误
+    pub fn new(
+        proxy: &Option<String>,
+        url: &str,
+        dns: Option<&str>,
+        timeout: Duration,
+    ) -> Result<Self, ReqError> {
+        let resolve = match dns {
+            Some(addr) => {
+                let host = url
+                    .parse::<Url>()
+                    .ok()
+                    .and_then(|u| u.host_str().map(|h| h.to_string()))
+                    .ok_or_else(|| ReqError::Build {
+                        url: url.to_string(),
+                        msg: "指定 dns 时 url 中缺少域名".to_string(),
+                    })?;
+                Some((host, addr.to_string()))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            proxy: proxy.clone(),
+            resolve,
+            resolver: None,
+            timeout,
+            tls: default_tls_options(),
+            options: ClientOptions::default(),
+        })
+    }
+
+    pub fn with_options(mut self, options: ClientOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn with_resolver(mut self, resolver: Arc<HostsResolver>) -> Self {
+        self.resolver = Some(ResolverRef(resolver));
+        self
+    }
+
+    pub fn with_tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = tls;
+        self
+    }
+}
+
+/// 按最近使用时间淘汰的 Client 缓存
+struct ClientCache {
+    capacity: usize,
+    tick: u64,
+    map: HashMap<ClientKey, (Client, u64)>,
+}
+
+impl ClientCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            tick: 0,
+            map: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &ClientKey) -> Option<Client> {
+        self.tick += 1;
+        let tick = self.tick;
+        self.map.get_mut(key).map(|(cli, used)| {
+            *used = tick;
+            cli.clone()
+        })
+    }
+
+    // 插入新建的 Client；其他线程已经插入时返回已有的，第二个值为是否插入
+    fn insert(&mut self, key: &ClientKey, cli: Client) -> (Client, bool) {
+        if let Some(cli) = self.get(key) {
+            return (cli, false);
+        }
+        self.shrink_to(self.capacity - 1);
+        self.map.insert(key.clone(), (cli.clone(), self.tick));
+        (cli, true)
+    }
+
+    // 淘汰最久未使用的，直到数量不超过 len
+    fn shrink_to(&mut self, len: usize) {
+        while self.map.len() > len {
+            let oldest = self
+                .map
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone());
+            match oldest {
+                Some(k) => self.map.remove(&k),
+                None => break,
+            };
+        }
+    }
+}
+
+/// 获取或新建 Client
+///
+/// # Panics
+///
+/// proxy / dns 不合法时 panic，不会退回为直连的 Client；需要处理错误时使用 [`try_get_or_create_client`]
+pub fn get_or_create_client(
+    proxy: &Option<String>,
+    url: &str,
+    dns: Option<&str>,
+    timeout: Duration,
+) -> Client {
+    try_get_or_create_client(proxy, url, dns, timeout)
+        .unwrap_or_else(|err| panic!("创建 proxy: {:?} dns: {:?} 的cli失败：{}", proxy, dns, err))
+}
+
+/// 获取或新建 Client，配置不合法时返回错误
+pub fn try_get_or_create_client(
+    proxy: &Option<String>,
+    url: &str,
+    dns: Option<&str>,
+    timeout: Duration,
+) -> Result<Client, ReqError> {
+    get_or_create_client_by_key(&ClientKey::new(proxy, url, dns, timeout)?)
+}
+
+pub fn get_or_create_client_by_key(key: &ClientKey) -> Result<Client, ReqError> {
+    client_for(key, false, &global_middlewares())
+}
+
+// 获取或新建 Client，新建时通知中间件；创建和通知都不持有锁
+pub(crate) fn client_for(
+    key: &ClientKey,
+    force_new: bool,
+    stack: &MiddlewareStack,
+) -> Result<Client, ReqError> {
+    if force_new {
+        let cli = try_build_client(key)?;
+        stack.on_client_created(key);
+        return Ok(cli);
+    }
+    if let Some(cli) = CLI.lock().get(key) {
+        return Ok(cli);
+    }
+
+    let cli = try_build_client(key)?;
+    let (cli, inserted) = CLI.lock().insert(key, cli);
+    if inserted {
+        stack.on_client_created(key);
+    }
+    Ok(cli)
+}
+
+/// 当前缓存中的所有 Client 配置
+pub fn cached_client_keys() -> Vec<ClientKey> {
+    CLI.lock().map.keys().cloned().collect()
+}
+
+/// 移除指定配置的 Client，返回是否存在
+pub fn evict_client(key: &ClientKey) -> bool {
+    CLI.lock().map.remove(key).is_some()
+}
+
+/// 设置缓存上限，超出的部分按最近使用时间淘汰
+pub fn set_client_cache_capacity(capacity: usize) {
+    let mut cache = CLI.lock();
+    cache.capacity = capacity.max(1);
+    let cap = cache.capacity;
+    cache.shrink_to(cap);
+}
+
+// 清理所有缓存中的cli
+pub fn clean_all_cli() {
+    CLI.lock().map.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(proxy: &str) -> ClientKey {
+        ClientKey::new(
+            &Some(proxy.to_string()),
+            "https://example.com/a",
+            None,
+            Duration::from_secs(1),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = ClientCache::new(2);
+        let (a, b, c) = (key("a"), key("b"), key("c"));
+        for k in [&a, &b, &a, &c] {
+            if cache.get(k).is_none() {
+                cache.insert(k, Client::new());
+            }
+        }
+
+        assert!(cache.map.contains_key(&a));
+        assert!(!cache.map.contains_key(&b));
+        assert!(cache.map.contains_key(&c));
+    }
+
+    #[test]
+    fn dns_is_part_of_key() {
+        let t = Duration::from_secs(1);
+        let a = ClientKey::new(&None, "https://a.com/x", Some("1.1.1.1:443"), t).unwrap();
+        let b = ClientKey::new(&None, "https://b.com/x", Some("1.1.1.1:443"), t).unwrap();
+        assert_ne!(a, b);
+        assert_eq!(
+            a.resolve,
+            Some(("a.com".to_string(), "1.1.1.1:443".to_string()))
+        );
+
+        let bad = ClientKey::new(&None, "https://a.com/x", Some("1.1.1.1"), t).unwrap();
+        assert!(try_build_client(&bad).is_err());
+        let bad = ClientKey::new(&Some("socks5://".to_string()), "https://a.com", None, t).unwrap();
+        assert!(try_build_client(&bad).is_err());
+    }
+
+    // 中间件在新建 Client 时再次访问缓存不会死锁
+    #[test]
+    fn hook_runs_outside_lock() {
+        struct Reentrant;
+
+        impl super::super::Middleware for Reentrant {
+            fn on_client_created(&self, key: &ClientKey) {
+                assert!(cached_client_keys().contains(key));
+            }
+        }
+
+        let k = key("socks5://127.0.0.1:1");
+        let stack = MiddlewareStack::new().with(Reentrant);
+        client_for(&k, false, &stack).unwrap();
+        client_for(&k, true, &stack).unwrap();
+        assert!(evict_client(&k));
+    }
+
+    // 代理不合法时不能退回为直连
+    #[test]
+    fn invalid_proxy_never_direct() {
+        let proxy = Some("socks5://".to_string());
+        let t = Duration::from_secs(1);
+        assert!(try_get_or_create_client(&proxy, "https://a.com", None, t).is_err());
+        let rs =
+            std::panic::catch_unwind(|| get_or_create_client(&proxy, "https://a.com", None, t));
+        assert!(rs.is_err());
+        assert!(!cached_client_keys().iter().any(|k| k.proxy == proxy));
+    }
+}
@@ -0,0 +1,462 @@
+//! 流式下载到文件：先写临时文件，完成校验后再改名，支持断点续传
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use data_encoding::HEXLOWER;
+use reqwest::header::{ACCEPT_ENCODING, CONTENT_RANGE, RANGE};
+use reqwest::{Response, StatusCode};
+use sha2::{Digest, Sha256};
+use tokio::fs::{self, File, OpenOptions};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::mpsc::UnboundedSender;
+
+use super::{ReqError, ReqResponse, RequestSpec};
+
+/// 下载进度
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DownloadProgress {
+    /// 已写入的字节数（包含续传前已有的部分）
+    pub downloaded: u64,
+    /// 文件总大小，服务端未返回时为 None
+    pub total: Option<u64>,
+}
+
+type ProgressFn = Arc<dyn Fn(DownloadProgress) + Send + Sync>;
+
+/// 等待响应头和每块数据的默认超时时间
+pub const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 整个下载过程的默认超时时间
+pub const DEFAULT_DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(24 * 3600);
+
+/// 下载选项
+#[derive(Clone)]
+pub struct DownloadOptions {
+    /// 临时文件存在时，使用 Range 请求从断点继续
+    pub resume: bool,
+    /// 等待响应头、每块数据的超时时间，超过时认为连接已断开
+    pub read_timeout: Duration,
+    /// 整个下载过程的超时时间，会覆盖 spec 的超时时间
+    pub timeout: Duration,
+    /// 期望的文件大小
+    pub expected_size: Option<u64>,
+    /// 期望的 sha256，hex 格式
+    pub sha256: Option<String>,
+    on_progress: Option<ProgressFn>,
+}
+
+impl Default for DownloadOptions {
+    fn default() -> Self {
+        Self {
+            resume: false,
+            read_timeout: DEFAULT_READ_TIMEOUT,
+            timeout: DEFAULT_DOWNLOAD_TIMEOUT,
+            expected_size: None,
+            sha256: None,
+            on_progress: None,
+        }
+    }
+}
+
+impl DownloadOptions {
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    pub fn expected_size(mut self, size: u64) -> Self {
+        self.expected_size = Some(size);
+        self
+    }
+
+    pub fn sha256(mut self, hex: impl Into<String>) -> Self {
+        self.sha256 = Some(hex.into().to_ascii_lowercase());
+        self
+    }
+
+    /// 每写入一块数据回调一次
+    pub fn on_progress(mut self, f: impl Fn(DownloadProgress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Arc::new(f));
+        self
+    }
+
+    /// 通过 channel 推送进度
+    pub fn progress_channel(self, tx: UnboundedSender<DownloadProgress>) -> Self {
+        self.on_progress(move |p| {
+            let _ = tx.send(p);
+        })
+    }
+
+    fn report(&self, p: DownloadProgress) {
+        if let Some(f) = &self.on_progress {
+            f(p)
+        }
+    }
+}
+
+impl fmt::Debug for DownloadOptions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DownloadOptions")
+            .field("resume", &self.resume)
+            .field("read_timeout", &self.read_timeout)
+            .field("timeout", &self.timeout)
+            .field("expected_size", &self.expected_size)
+            .field("sha256", &self.sha256)
+            .finish()
+    }
+}
+
+/// 下载结果
+#[derive(Debug, Clone)]
+pub struct DownloadReport {
+    pub path: PathBuf,
+    pub size: u64,
+    /// 从多少字节处续传，0 表示完整下载
+    pub resumed_from: u64,
+    pub sha256: String,
+}
+
+/// 临时文件路径：原文件名加 .part
+pub fn part_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".part");
+    path.with_file_name(name)
+}
+
+fn io_err(path: &Path, err: impl fmt::Display) -> ReqError {
+    ReqError::Io {
+        path: path.display().to_string(),
+        msg: err.to_string(),
+    }
+}
+
+/// 按 spec 下载到 path
+///
+/// spec 的超时时间会被 opts.timeout 覆盖，读取过程中每块数据的等待时间不超过 opts.read_timeout
+pub async fn download(
+    spec: RequestSpec,
+    path: impl AsRef<Path>,
+    opts: DownloadOptions,
+) -> Result<DownloadReport, ReqError> {
+    let path = path.as_ref();
+    let part = part_path(path);
+    let url = spec.url.clone();
+    let spec = spec.timeout(opts.timeout);
+
+    let offset = match fs::metadata(&part).await {
+        Ok(m) if opts.resume => m.len(),
+        _ => 0,
+    };
+    let (resp, offset, total) = match offset {
+        0 => (None, 0, None),
+        n => resume_from(&spec, n, &opts).await?,
+    };
+    // 无法续传时从头下载
+    let (resp, offset, total) = match resp {
+        Some(resp) => (Some(resp), offset, total),
+        None if offset > 0 => (None, offset, total),
+        None => {
+            let resp = execute(spec, &opts).await?;
+            if !resp.status().is_success() {
+                return Err(ReqResponse::read(resp, &url).await?.into_status_error());
+            }
+            let total = resp.content_length();
+            (Some(resp), 0, total)
+        }
+    };
+
+    let mut hasher = Sha256::new();
+    let mut downloaded = offset;
+    if offset > 0 {
+        hash_file(&part, &mut hasher).await?;
+    }
+    // resp 为 None 表示临时文件已经完整
+    if let Some(mut resp) = resp {
+        let mut file = if offset > 0 {
+            OpenOptions::new()
+                .append(true)
+                .open(&part)
+                .await
+                .map_err(|err| io_err(&part, err))?
+        } else {
+            File::create(&part)
+                .await
+                .map_err(|err| io_err(&part, err))?
+        };
+
+        loop {
+            let chunk = tokio::time::timeout(opts.read_timeout, resp.chunk())
+                .await
+                .map_err(|_| ReqError::Timeout { url: url.clone() })?
+                .map_err(|err| ReqError::from_reqwest(err, &url, &None))?;
+            let chunk = match chunk {
+                Some(c) => c,
+                None => break,
+            };
+            file.write_all(&chunk)
+                .await
+                .map_err(|err| io_err(&part, err))?;
+            hasher.update(&chunk);
+            downloaded += chunk.len() as u64;
+            opts.report(DownloadProgress { downloaded, total });
+        }
+        file.sync_all().await.map_err(|err| io_err(&part, err))?;
+    }
+
+    let sha256 = HEXLOWER.encode(&hasher.finalize());
+    if let Some(msg) = verify(&opts, total, downloaded, &sha256) {
+        let _ = fs::remove_file(&part).await;
+        return Err(ReqError::Decode { url, msg });
+    }
+
+    fs::rename(&part, path)
+        .await
+        .map_err(|err| io_err(path, err))?;
+    Ok(DownloadReport {
+        path: path.to_path_buf(),
+        size: downloaded,
+        resumed_from: offset,
+        sha256,
+    })
+}
+
+// 从 offset 处续传：返回 (响应, 续传位置, 总大小)
+// - 206 且 Content-Range 起始位置正确：继续写入
+// - 416 且临时文件已经完整：响应为 None
+// - 服务端不支持 Range 或位置不一致：返回 200 的完整响应，位置为 0；
+//   416 但大小不一致时响应为 None、位置为 0，由调用方重新下载
+async fn resume_from(
+    spec: &RequestSpec,
+    offset: u64,
+    opts: &DownloadOptions,
+) -> Result<(Option<Response>, u64, Option<u64>), ReqError> {
+    // 压缩后的内容无法按字节续传
+    let ranged = spec
+        .clone()
+        .header(RANGE.as_str(), format!("bytes={}-", offset))
+        .header(ACCEPT_ENCODING.as_str(), "identity");
+    let resp = execute(ranged, opts).await?;
+    let range = content_range(&resp);
+
+    match resp.status() {
+        StatusCode::PARTIAL_CONTENT if range.map(|(start, _)| start) == Some(offset) => {
+            Ok((Some(resp), offset, range.and_then(|(_, total)| total)))
+        }
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            let total = range.and_then(|(_, total)| total).or(opts.expected_size);
+            match total == Some(offset) {
+                true => Ok((None, offset, total)),
+                false => Ok((None, 0, None)),
+            }
+        }
+        s if s.is_success() && s != StatusCode::PARTIAL_CONTENT => {
+            let total = resp.content_length();
+            Ok((Some(resp), 0, total))
+        }
+        // 206 但起始位置不对，放弃已有部分
+        StatusCode::PARTIAL_CONTENT => Ok((None, 0, None)),
+        _ => Err(ReqResponse::read(resp, &spec.url)
+            .await?
+            .into_status_error()),
+    }
+}
+
+// 等待响应头，超过 read_timeout 时返回超时错误
+async fn execute(spec: RequestSpec, opts: &DownloadOptions) -> Result<Response, ReqError> {
+    let url = spec.url.clone();
+    tokio::time::timeout(opts.read_timeout, spec.execute())
+        .await
+        .map_err(|_| ReqError::Timeout { url })?
+}
+
+// 解析 Content-Range：`bytes 100-199/200` 返回 (100, Some(200))，`bytes */200` 返回 (0, Some(200))
+fn content_range(resp: &Response) -> Option<(u64, Option<u64>)> {
+    let v = resp.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+    let (range, total) = v.trim().strip_prefix("bytes")?.trim().split_once('/')?;
+    let total = total.trim().parse::<u64>().ok();
+    let start = match range.trim() {
+        "*" => 0,
+        r => r.split('-').next()?.trim().parse().ok()?,
+    };
+    Some((start, total))
+}
+
+/// 使用默认配置下载 url 到 path
+pub async fn download_to_file(
+    url: &str,
+    path: impl AsRef<Path>,
+    proxy: &Option<String>,
+    opts: DownloadOptions,
+) -> Result<DownloadReport, ReqError> {
+    download(RequestSpec::get(url).proxy(proxy), path, opts).await
+}
+
+// 续传时先计算已有部分的 hash
+async fn hash_file(path: &Path, hasher: &mut Sha256) -> Result<(), ReqError> {
+    let mut f = File::open(path).await.map_err(|err| io_err(path, err))?;
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let n = f.read(&mut buf).await.map_err(|err| io_err(path, err))?;
+        if n == 0 {
+            return Ok(());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+// 校验大小和 hash，失败时返回原因
+fn verify(opts: &DownloadOptions, total: Option<u64>, size: u64, sha256: &str) -> Option<String> {
+    if let Some(expected) = opts.expected_size.or(total) {
+        if expected != size {
+            return Some(format!("文件大小不一致：期望 {} 实际 {}", expected, size));
+        }
+    }
+    match &opts.sha256 {
+        Some(expected) if expected != sha256 => {
+            Some(format!("sha256 不一致：期望 {} 实际 {}", expected, sha256))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::req::{with_transport, MockReply, MockTransport};
+    use parking_lot::Mutex;
+
+    const URL: &str = "http://mock.invalid/file.bin";
+
+    fn temp(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("download_{}_{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("file.bin")
+    }
+
+    fn sha(data: &[u8]) -> String {
+        HEXLOWER.encode(&Sha256::digest(data))
+    }
+
+    fn header<'a>(ctx: &'a super::super::ReqContext, k: &str) -> Option<&'a str> {
+        ctx.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(k))
+            .map(|(_, v)| v.as_str())
+    }
+
+    async fn run(
+        mock: MockTransport,
+        path: &Path,
+        opts: DownloadOptions,
+    ) -> (Result<DownloadReport, ReqError>, Arc<MockTransport>) {
+        let mock = Arc::new(mock);
+        let rs = with_transport(mock.clone(), download_to_file(URL, path, &None, opts)).await;
+        (rs, mock)
+    }
+
+    #[tokio::test]
+    async fn fresh_download_with_progress() {
+        let path = temp("fresh");
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let s = seen.clone();
+        let opts = DownloadOptions::default()
+            .sha256(sha(b"hello"))
+            .on_progress(move |p| s.lock().push(p));
+        let (rs, mock) = run(MockTransport::new().get(URL, "hello"), &path, opts).await;
+
+        let report = rs.unwrap();
+        assert_eq!((report.size, report.resumed_from), (5, 0));
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        assert!(!part_path(&path).exists());
+        assert_eq!(header(&mock.requests()[0], "range"), None);
+        let last = *seen.lock().last().unwrap();
+        assert_eq!(
+            last,
+            DownloadProgress {
+                downloaded: 5,
+                total: Some(5)
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_with_partial_content() {
+        let path = temp("resume");
+        std::fs::write(part_path(&path), b"hel").unwrap();
+        let reply = MockReply::status(StatusCode::PARTIAL_CONTENT, "lo")
+            .header("content-range", "bytes 3-4/5");
+        let opts = DownloadOptions::default()
+            .resume(true)
+            .sha256(sha(b"hello"));
+        let (rs, mock) = run(MockTransport::new().script(URL, [reply]), &path, opts).await;
+
+        let report = rs.unwrap();
+        assert_eq!((report.size, report.resumed_from), (5, 3));
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        let req = &mock.requests()[0];
+        assert_eq!(header(req, "range"), Some("bytes=3-"));
+        assert_eq!(header(req, "accept-encoding"), Some("identity"));
+    }
+
+    #[tokio::test]
+    async fn range_ignored_or_mismatched_restarts() {
+        // 服务端忽略 Range，返回完整内容
+        let path = temp("ignored");
+        std::fs::write(part_path(&path), b"xxx").unwrap();
+        let opts = DownloadOptions::default().resume(true);
+        let (rs, _) = run(MockTransport::new().get(URL, "hello"), &path, opts).await;
+        assert_eq!(rs.unwrap().resumed_from, 0);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+
+        // Content-Range 起始位置不一致，重新请求完整内容
+        let path = temp("mismatch");
+        std::fs::write(part_path(&path), b"xxx").unwrap();
+        let reply = MockReply::status(StatusCode::PARTIAL_CONTENT, "llo")
+            .header("content-range", "bytes 2-4/5");
+        let mock = MockTransport::new().get(URL, "hello").script(URL, [reply]);
+        let (rs, mock) = run(mock, &path, DownloadOptions::default().resume(true)).await;
+        assert_eq!(rs.unwrap().resumed_from, 0);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        assert_eq!(header(&mock.requests()[1], "range"), None);
+    }
+
+    #[tokio::test]
+    async fn range_not_satisfiable_when_complete() {
+        let path = temp("complete");
+        std::fs::write(part_path(&path), b"hello").unwrap();
+        let reply = MockReply::status(StatusCode::RANGE_NOT_SATISFIABLE, "")
+            .header("content-range", "bytes */5");
+        let opts = DownloadOptions::default()
+            .resume(true)
+            .sha256(sha(b"hello"));
+        let (rs, mock) = run(MockTransport::new().script(URL, [reply]), &path, opts).await;
+
+        assert_eq!(rs.unwrap().size, 5);
+        assert_eq!(std::fs::read(&path).unwrap(), b"hello");
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sha256_mismatch_removes_part() {
+        let path = temp("sha");
+        let opts = DownloadOptions::default().sha256(sha(b"other"));
+        let (rs, _) = run(MockTransport::new().get(URL, "hello"), &path, opts).await;
+
+        assert!(matches!(rs, Err(ReqError::Decode { .. })));
+        assert!(!part_path(&path).exists());
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,240 @@
+//! 请求错误类型
+
+use std::fmt;
+
+use reqwest::header::HeaderMap;
+use reqwest::StatusCode;
+
+/// 请求失败的具体原因，调用方可按类型做重试、告警
+#[derive(Debug, Clone)]
+pub enum ReqError {
+    /// 请求参数不合法，未发出请求
+    Build { url: String, msg: String },
+    /// 建立连接或发送请求失败
+    Connect { url: String, msg: String },
+    /// 请求超时
+    Timeout { url: String },
+    /// 服务端返回非成功状态码
+    Status {
+        url: String,
+        code: StatusCode,
+        body: String,
+        headers: Box<HeaderMap>,
+    },
+    /// 读取或解析响应内容失败
+    Decode { url: String, msg: String },
+    /// 响应内容反序列化失败；path 为出错字段路径，snippet 为出错位置附近的内容
+    Parse {
+        url: String,
+        path: String,
+        snippet: String,
+        msg: String,
+    },
+    /// 代理配置错误或代理连接失败
+    Proxy { proxy: String, msg: String },
+    /// 读写本地文件失败
+    Io { path: String, msg: String },
+}
+
+/// 错误类型，不带具体内容，便于匹配和配置
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReqErrorKind {
+    Build,
+    Connect,
+    Timeout,
+    Status,
+    Decode,
+    Parse,
+    Proxy,
+    Io,
+}
+
+impl ReqErrorKind {
+    /// 用于日志和指标标签
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReqErrorKind::Build => "build",
+            ReqErrorKind::Connect => "connect",
+            ReqErrorKind::Timeout => "timeout",
+            ReqErrorKind::Status => "status",
+            ReqErrorKind::Decode => "decode",
+            ReqErrorKind::Parse => "parse",
+            ReqErrorKind::Proxy => "proxy",
+            ReqErrorKind::Io => "io",
+        }
+    }
+}
+
+impl ReqError {
+    /// 将 reqwest 错误按类型转换；proxy 为本次请求使用的代理
+    pub fn from_reqwest(err: reqwest::Error, url: &str, proxy: &Option<String>) -> Self {
+        let url = err
+            .url()
+            .map(|u| u.to_string())
+            .unwrap_or_else(|| url.to_string());
+
+        if err.is_builder() {
+            return ReqError::Build {
+                url,
+                msg: err.to_string(),
+            };
+        }
+        if err.is_timeout() {
+            return ReqError::Timeout { url };
+        }
+        if err.is_decode() || err.is_body() {
+            return ReqError::Decode {
+                url,
+                msg: err.to_string(),
+            };
+        }
+        if err.is_connect() {
+            // 经过远程代理时，连接失败一般是代理本身不可用
+            if let Some(p) = proxy.as_ref().filter(|p| !p.starts_with("local")) {
+                return ReqError::Proxy {
+                    proxy: p.clone(),
+                    msg: err.to_string(),
+                };
+            }
+        }
+
+        ReqError::Connect {
+            url,
+            msg: err.to_string(),
+        }
+    }
+
+    pub fn kind(&self) -> ReqErrorKind {
+        match self {
+            ReqError::Build { .. } => ReqErrorKind::Build,
+            ReqError::Connect { .. } => ReqErrorKind::Connect,
+            ReqError::Timeout { .. } => ReqErrorKind::Timeout,
+            ReqError::Status { .. } => ReqErrorKind::Status,
+            ReqError::Decode { .. } => ReqErrorKind::Decode,
+            ReqError::Parse { .. } => ReqErrorKind::Parse,
+            ReqError::Proxy { .. } => ReqErrorKind::Proxy,
+            ReqError::Io { .. } => ReqErrorKind::Io,
+        }
+    }
+
+    /// 非成功状态码时返回状态码
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ReqError::Status { code, .. } => Some(*code),
+            _ => None,
+        }
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        self.kind() == ReqErrorKind::Timeout
+    }
+}
+
+impl From<reqwest::Error> for ReqError {
+    fn from(err: reqwest::Error) -> Self {
+        ReqError::from_reqwest(err, "", &None)
+    }
+}
+
+impl fmt::Display for ReqError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReqError::Build { url, msg } => write!(f, "请求url：{} 参数错误：{}", url, msg),
+            ReqError::Connect { url, msg } => write!(f, "请求url：{} 连接失败：{}", url, msg),
+            ReqError::Timeout { url } => write!(f, "请求url：{} 请求超时", url),
+            ReqError::Status {
+                url, code, body, ..
+            } => write!(f, "请求url：{} 状态码：{} 错误信息 {}", url, code, body),
+            ReqError::Decode { url, msg } => write!(f, "请求url：{} 读取响应失败：{}", url, msg),
+            ReqError::Parse {
+                url,
+                path,
+                snippet,
+                msg,
+            } => write!(
+                f,
+                "请求url：{} 解析字段 {} 失败：{} 内容：{}",
+                url, path, msg, snippet
+            ),
+            ReqError::Proxy { proxy, msg } => write!(f, "代理：{} 错误：{}", proxy, msg),
+            ReqError::Io { path, msg } => write!(f, "文件：{} 读写失败：{}", path, msg),
+        }
+    }
+}
+
+impl std::error::Error for ReqError {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    // 没有监听的本地端口
+    async fn closed_port() -> String {
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        format!("http://{}/", addr)
+    }
+
+    // 收到请求后返回 reply，reply 为 None 时不响应
+    async fn server(reply: Option<&'static str>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut s, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 1024];
+                    let _ = s.read(&mut buf).await;
+                    match reply {
+                        Some(r) => s.write_all(r.as_bytes()).await.unwrap(),
+                        None => tokio::time::sleep(Duration::from_secs(5)).await,
+                    }
+                });
+            }
+        });
+        format!("http://{}/", addr)
+    }
+
+    async fn classify(url: &str, proxy: Option<String>, timeout: Duration) -> ReqError {
+        let mut builder = reqwest::Client::builder().timeout(timeout);
+        if let Some(p) = &proxy {
+            builder = builder.proxy(reqwest::Proxy::all(p.as_str()).unwrap());
+        }
+        let err = match builder.build().unwrap().get(url).send().await {
+            Ok(resp) => resp.json::<serde_json::Value>().await.unwrap_err(),
+            Err(err) => err,
+        };
+        ReqError::from_reqwest(err, url, &proxy)
+    }
+
+    #[tokio::test]
+    async fn classify_reqwest_errors() {
+        let second = Duration::from_secs(1);
+        let closed = closed_port().await;
+        let err = classify(&closed, None, second).await;
+        assert_eq!(err.kind(), ReqErrorKind::Connect);
+
+        // 经过远程代理时归为代理错误
+        let proxy = closed_port().await;
+        let err = classify("http://example.com/", Some(proxy.clone()), second).await;
+        assert!(matches!(err, ReqError::Proxy { proxy: p, .. } if p == proxy));
+
+        let silent = server(None).await;
+        let err = classify(&silent, None, Duration::from_millis(100)).await;
+        assert!(matches!(err, ReqError::Timeout { url } if url == silent));
+
+        let bad_json = "HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{x";
+        let err = classify(&server(Some(bad_json)).await, None, second).await;
+        assert_eq!(err.kind(), ReqErrorKind::Decode);
+
+        let err = classify("not a url", None, second).await;
+        assert_eq!(err.kind(), ReqErrorKind::Build);
+    }
+}
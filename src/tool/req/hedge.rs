@@ -0,0 +1,189 @@
+//! 对冲请求：同一个请求经过多个代理 / 本地出口 ip 发送，使用最先成功的响应
+//!
+//! ```ignore
+//! let routes = vec![Some("local://10.0.0.2".to_string()), Some("socks5://p1:1080".to_string())];
+//! let hedged = hedged_send(RequestSpec::get(url), &routes, Duration::from_millis(20)).await?;
+//! info!("胜出线路 {:?} 耗时 {:?}", hedged.route, hedged.latency);
+//! ```
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use tracing::debug;
+
+use super::{ReqError, ReqResponse, RequestSpec};
+
+/// 对冲请求的结果
+#[derive(Debug, Clone)]
+pub struct Hedged {
+    /// 胜出的线路，None 表示直连
+    pub route: Option<String>,
+    /// 胜出线路在 routes 中的下标
+    pub index: usize,
+    /// 从开始发送到收到胜出响应的耗时
+    pub latency: Duration,
+    pub response: ReqResponse,
+}
+
+/// 依次经过 routes 中的线路发送同一个请求，每隔 stagger 多发一路，
+/// 返回最先成功的响应，其余未完成的请求会被取消
+///
+/// 正在发送的请求全部失败时立即发下一路，不再等待 stagger；
+/// 所有线路都失败时返回最后一个错误
+pub async fn hedged_send(
+    spec: RequestSpec,
+    routes: &[Option<String>],
+    stagger: Duration,
+) -> Result<Hedged, ReqError> {
+    let start = Instant::now();
+    let rs = staggered_first_ok(routes.len(), stagger, |i| {
+        spec.clone().proxy(&routes[i]).send()
+    })
+    .await;
+    match rs {
+        Some(Ok((i, response))) => {
+            debug!("对冲请求 {} 胜出线路：{:?}", spec.url, routes[i]);
+            Ok(Hedged {
+                route: routes[i].clone(),
+                index: i,
+                latency: start.elapsed(),
+                response,
+            })
+        }
+        Some(Err(err)) => Err(err),
+        None => Err(ReqError::Build {
+            url: spec.url.clone(),
+            msg: "对冲请求至少需要一条线路".to_string(),
+        }),
+    }
+}
+
+/// 依次启动 launch(0..n)，每隔 stagger 多启动一个，返回最先成功的下标和结果，其余未完成的会被取消
+///
+/// 正在进行的全部失败时立即启动下一个，不再等待 stagger；
+/// 全部失败时返回最后一个错误，n 为 0 时返回 None
+pub async fn staggered_first_ok<T, E, F, Fut>(
+    n: usize,
+    stagger: Duration,
+    mut launch: F,
+) -> Option<Result<(usize, T), E>>
+where
+    F: FnMut(usize) -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    if n == 0 {
+        return None;
+    }
+    let mut start = |i: usize| {
+        let fut = launch(i);
+        async move { (i, fut.await) }
+    };
+
+    let mut pending = FuturesUnordered::new();
+    pending.push(start(0));
+    let mut next = 1;
+    let mut next_at = tokio::time::Instant::now() + stagger;
+
+    loop {
+        tokio::select! {
+            Some((i, rs)) = pending.next() => match rs {
+                Ok(v) => return Some(Ok((i, v))),
+                Err(err) => {
+                    if pending.is_empty() {
+                        if next >= n {
+                            return Some(Err(err));
+                        }
+                        pending.push(start(next));
+                        next += 1;
+                        next_at = tokio::time::Instant::now() + stagger;
+                    }
+                }
+            },
+            _ = tokio::time::sleep_until(next_at), if next < n => {
+                pending.push(start(next));
+                next += 1;
+                next_at = tokio::time::Instant::now() + stagger;
+            }
+        }
+    }
+}
+
+/// GET 请求的对冲发送
+pub async fn hedged_get(
+    url: &str,
+    routes: &[Option<String>],
+    stagger: Duration,
+    timeout: Duration,
+) -> Result<Hedged, ReqError> {
+    hedged_send(RequestSpec::get(url).timeout(timeout), routes, stagger).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use reqwest::{Response, StatusCode};
+
+    use super::*;
+    use crate::tool::req::{mock_response, Middleware, ReqContext};
+
+    // 经过 local://10.0.0.1 的请求连接失败，其余返回线路名
+    #[derive(Default)]
+    struct Routes(AtomicUsize);
+
+    impl Middleware for Arc<Routes> {
+        fn before_send(&self, ctx: &mut ReqContext) -> Result<Option<Response>, ReqError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            let proxy = ctx.proxy.clone().unwrap_or_default();
+            if proxy.contains("10.0.0.1") {
+                return Err(ReqError::Connect {
+                    url: ctx.url.clone(),
+                    msg: "refused".to_string(),
+                });
+            }
+            Ok(Some(mock_response(StatusCode::OK, proxy)))
+        }
+    }
+
+    fn routes(list: &[&str]) -> Vec<Option<String>> {
+        list.iter().map(|r| Some(r.to_string())).collect()
+    }
+
+    #[tokio::test]
+    async fn failover_to_next_route() {
+        let m = Arc::new(Routes::default());
+        let spec = RequestSpec::get("http://mock.invalid/").middleware(m.clone());
+        let rs = hedged_send(
+            spec,
+            &routes(&["local://10.0.0.1", "local://10.0.0.2"]),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rs.index, 1);
+        assert_eq!(rs.response.text(), "local://10.0.0.2");
+        // 第一路失败后立即发第二路
+        assert!(rs.latency < Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn first_success_wins() {
+        let m = Arc::new(Routes::default());
+        let spec = RequestSpec::get("http://mock.invalid/").middleware(m.clone());
+        let rs = hedged_send(
+            spec,
+            &routes(&["local://10.0.0.2", "local://10.0.0.3"]),
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        assert_eq!(rs.route.as_deref(), Some("local://10.0.0.2"));
+        assert_eq!(m.0.load(Ordering::SeqCst), 1);
+
+        let spec = RequestSpec::get("http://mock.invalid/").middleware(m.clone());
+        let err = hedged_send(spec, &routes(&["local://10.0.0.1"]), Duration::ZERO).await;
+        assert!(matches!(err, Err(ReqError::Connect { .. })));
+    }
+}
@@ -0,0 +1,261 @@
+//! 按域名限制请求频率，避免被上游封禁
+//!
+//! 配置示例（toml）：
+//! ```toml
+//! [default]
+//! capacity = 1200
+//! window = "1m"
+//!
+//! [hosts."fapi.binance.com"]
+//! capacity = 2400
+//! window = "1m"
+//! usage_header = "x-mbx-used-weight-1m"
+//! weights = { "/fapi/v1/depth" = 10, "/fapi/v1/exchangeInfo" = 40 }
+//! ```
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use reqwest::header::HeaderMap;
+use serde::Deserialize;
+use tracing::debug;
+
+use crate::tool::config::load_toml;
+use crate::tool::deserialize_duration;
+
+static LIMITER: Lazy<RwLock<Option<Arc<RateLimiter>>>> = Lazy::new(Default::default);
+
+/// 单个域名的限频规则：window 时间内最多使用 capacity 权重
+#[derive(Debug, Clone, Deserialize)]
+pub struct LimitRule {
+    pub capacity: u32,
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub window: Duration,
+    /// 接口路径对应的权重，未配置的为 1
+    #[serde(default)]
+    pub weights: HashMap<String, u32>,
+    /// 响应头中服务端统计的已用权重，例如 x-mbx-used-weight-1m
+    #[serde(default)]
+    pub usage_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RateLimitConfig {
+    /// 未单独配置的域名使用的规则；为空时不限制
+    #[serde(default)]
+    pub default: Option<LimitRule>,
+    #[serde(default)]
+    pub hosts: HashMap<String, LimitRule>,
+}
+
+// 滑动窗口内的请求记录
+#[derive(Debug, Default)]
+struct Window {
+    log: VecDeque<(Instant, u32)>,
+    used: u32,
+}
+
+impl Window {
+    fn purge(&mut self, now: Instant, window: Duration) {
+        while let Some((t, w)) = self.log.front() {
+            if now.duration_since(*t) < window {
+                break;
+            }
+            self.used = self.used.saturating_sub(*w);
+            self.log.pop_front();
+        }
+    }
+
+    fn push(&mut self, now: Instant, weight: u32) {
+        self.log.push_back((now, weight));
+        // 权重由调用方和服务端响应决定，避免溢出
+        self.used = self.used.saturating_add(weight);
+    }
+}
+
+/// 按域名的滑动窗口限频器
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        Self {
+            config,
+            windows: Default::default(),
+        }
+    }
+
+    /// 从 toml 文本加载
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Ok(Self::new(load_toml(content)?))
+    }
+
+    pub fn rule(&self, host: &str) -> Option<&LimitRule> {
+        self.config.hosts.get(host).or(self.config.default.as_ref())
+    }
+
+    /// 接口路径对应的权重
+    pub fn weight_of(&self, host: &str, path: &str) -> u32 {
+        self.rule(host)
+            .and_then(|r| r.weights.get(path).copied())
+            .unwrap_or(1)
+    }
+
+    /// 当前窗口内已使用的权重
+    pub fn used(&self, host: &str) -> u32 {
+        let rule = match self.rule(host) {
+            Some(r) => r,
+            None => return 0,
+        };
+        let mut windows = self.windows.lock();
+        match windows.get_mut(host) {
+            Some(w) => {
+                w.purge(Instant::now(), rule.window);
+                w.used
+            }
+            None => 0,
+        }
+    }
+
+    /// 申请 weight 权重，额度不足时等待
+    pub async fn acquire(&self, host: &str, weight: u32) {
+        let rule = match self.rule(host) {
+            Some(r) => r,
+            None => return,
+        };
+
+        loop {
+            let wait = {
+                let now = Instant::now();
+                let mut windows = self.windows.lock();
+                let w = windows.entry(host.to_string()).or_default();
+                w.purge(now, rule.window);
+
+                // 单次权重超过上限时，等窗口清空后放行
+                if w.used.saturating_add(weight) <= rule.capacity || w.log.is_empty() {
+                    w.push(now, weight);
+                    return;
+                }
+
+                // 等到足够多的记录过期
+                let mut free = rule.capacity.saturating_sub(w.used);
+                let mut until = now;
+                for (t, used) in w.log.iter() {
+                    free = free.saturating_add(*used);
+                    until = *t + rule.window;
+                    if free >= weight {
+                        break;
+                    }
+                }
+                until.saturating_duration_since(now)
+            };
+
+            debug!("{} 请求频率超限，等待 {:?}", host, wait);
+            tokio::time::sleep(wait.max(Duration::from_millis(1))).await;
+        }
+    }
+
+    /// 根据响应头中服务端统计的用量修正本地计数
+    pub fn observe(&self, host: &str, headers: &HeaderMap) {
+        let rule = match self.rule(host) {
+            Some(r) => r,
+            None => return,
+        };
+        let reported = match rule
+            .usage_header
+            .as_ref()
+            .and_then(|h| headers.get(h.as_str()))
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u32>().ok())
+        {
+            Some(v) => v,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let mut windows = self.windows.lock();
+        let w = windows.entry(host.to_string()).or_default();
+        w.purge(now, rule.window);
+        // 其他进程共用同一出口时，服务端统计会比本地多
+        if reported > w.used {
+            w.push(now, reported - w.used);
+        }
+    }
+}
+
+/// 设置全局限频器，所有经过 RequestSpec 的请求都会使用
+pub fn set_rate_limiter(limiter: Option<RateLimiter>) {
+    *LIMITER.write() = limiter.map(Arc::new);
+}
+
+pub fn rate_limiter() -> Option<Arc<RateLimiter>> {
+    LIMITER.read().clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONF: &str = r#"
+[default]
+capacity = 100
+window = "1m"
+
+[hosts."fapi.binance.com"]
+capacity = 3
+window = "200ms"
+usageHeader = "x-mbx-used-weight-1m"
+weights = { "/fapi/v1/depth" = 2 }
+"#;
+
+    #[tokio::test]
+    async fn limit_by_host() {
+        let l = RateLimiter::from_toml(CONF).unwrap();
+        let host = "fapi.binance.com";
+        assert_eq!(l.weight_of(host, "/fapi/v1/depth"), 2);
+        assert_eq!(l.weight_of(host, "/fapi/v1/time"), 1);
+        assert_eq!(l.rule("other.com").unwrap().capacity, 100);
+
+        let start = Instant::now();
+        l.acquire(host, 2).await;
+        l.acquire(host, 1).await;
+        assert!(start.elapsed() < Duration::from_millis(100));
+        l.acquire(host, 2).await;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn observe_usage_header() {
+        let l = RateLimiter::from_toml(CONF).unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-mbx-used-weight-1m", "3".parse().unwrap());
+        l.observe("fapi.binance.com", &headers);
+        assert_eq!(l.used("fapi.binance.com"), 3);
+
+        // 服务端返回异常大的用量时不溢出
+        headers.insert(
+            "x-mbx-used-weight-1m",
+            u32::MAX.to_string().parse().unwrap(),
+        );
+        l.observe("fapi.binance.com", &headers);
+        assert_eq!(l.used("fapi.binance.com"), u32::MAX);
+    }
+
+    #[tokio::test]
+    async fn huge_weight_does_not_overflow() {
+        let l = RateLimiter::from_toml(CONF).unwrap();
+        let host = "fapi.binance.com";
+        l.acquire(host, 1).await;
+        let start = Instant::now();
+        l.acquire(host, u32::MAX).await;
+        assert!(start.elapsed() >= Duration::from_millis(150));
+        assert_eq!(l.used(host), u32::MAX);
+    }
+}
@@ -0,0 +1,189 @@
+//! 请求指标：按域名、状态统计请求数、流量和耗时分布，可导出为 Prometheus 文本格式
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use super::ReqError;
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+/// 耗时分布的桶上限，单位秒
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// 单个 (域名, 状态) 的统计
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Series {
+    pub count: u64,
+    pub bytes: u64,
+    /// 耗时总和，单位秒
+    pub latency_sum: f64,
+    /// 与 LATENCY_BUCKETS 对应，每个桶单独计数（非累计）
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+}
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    series: Mutex<BTreeMap<(String, String), Series>>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 记录一次请求；status 为状态码或错误类型，例如 200、timeout
+    pub fn observe(&self, host: &str, status: &str, latency: Duration, bytes: u64) {
+        let secs = latency.as_secs_f64();
+        let mut series = self.series.lock();
+        let s = series
+            .entry((host.to_string(), status.to_string()))
+            .or_default();
+        s.count += 1;
+        s.bytes += bytes;
+        s.latency_sum += secs;
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|le| secs <= *le) {
+            s.buckets[i] += 1;
+        }
+    }
+
+    pub fn get(&self, host: &str, status: &str) -> Option<Series> {
+        self.series
+            .lock()
+            .get(&(host.to_string(), status.to_string()))
+            .cloned()
+    }
+
+    pub fn snapshot(&self) -> Vec<(String, String, Series)> {
+        self.series
+            .lock()
+            .iter()
+            .map(|((h, st), s)| (h.clone(), st.clone(), s.clone()))
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        self.series.lock().clear();
+    }
+
+    /// 导出为 Prometheus 文本格式
+    pub fn render_prometheus(&self) -> String {
+        let snapshot = self.snapshot();
+        let mut out = String::new();
+
+        out.push_str("# HELP http_client_requests_total 请求总数\n");
+        out.push_str("# TYPE http_client_requests_total counter\n");
+        for (h, st, s) in snapshot.iter() {
+            let _ = writeln!(
+                out,
+                "http_client_requests_total{} {}",
+                labels(h, st),
+                s.count
+            );
+        }
+
+        out.push_str("# HELP http_client_response_bytes_total 响应字节数\n");
+        out.push_str("# TYPE http_client_response_bytes_total counter\n");
+        for (h, st, s) in snapshot.iter() {
+            let _ = writeln!(
+                out,
+                "http_client_response_bytes_total{} {}",
+                labels(h, st),
+                s.bytes
+            );
+        }
+
+        out.push_str("# HELP http_client_request_duration_seconds 请求耗时\n");
+        out.push_str("# TYPE http_client_request_duration_seconds histogram\n");
+        for (h, st, s) in snapshot.iter() {
+            let base = format!("host=\"{}\",status=\"{}\"", escape(h), escape(st));
+            let mut acc = 0;
+            for (le, n) in LATENCY_BUCKETS.iter().zip(s.buckets.iter()) {
+                acc += n;
+                let _ = writeln!(
+                    out,
+                    "http_client_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    base, le, acc
+                );
+            }
+            let _ = writeln!(
+                out,
+                "http_client_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                base, s.count
+            );
+            let _ = writeln!(
+                out,
+                "http_client_request_duration_seconds_sum{{{}}} {}",
+                base, s.latency_sum
+            );
+            let _ = writeln!(
+                out,
+                "http_client_request_duration_seconds_count{{{}}} {}",
+                base, s.count
+            );
+        }
+        out
+    }
+}
+
+/// 全局指标，所有经过 RequestSpec 的请求都会记录
+pub fn metrics() -> &'static Metrics {
+    &METRICS
+}
+
+/// 请求结果对应的状态标签：状态码或错误类型
+pub fn status_label(rs: Result<u16, &ReqError>) -> String {
+    match rs {
+        Ok(code) => code.to_string(),
+        Err(err) => match err.status() {
+            Some(code) => code.as_u16().to_string(),
+            None => err.kind().as_str().to_string(),
+        },
+    }
+}
+
+fn labels(host: &str, status: &str) -> String {
+    format!(
+        "{{host=\"{}\",status=\"{}\"}}",
+        escape(host),
+        escape(status)
+    )
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn observe_and_render() {
+        let m = Metrics::new();
+        m.observe("a.com", "200", Duration::from_millis(3), 10);
+        m.observe("a.com", "200", Duration::from_millis(80), 20);
+        m.observe("a.com", "timeout", Duration::from_secs(30), 0);
+
+        let s = m.get("a.com", "200").unwrap();
+        assert_eq!((s.count, s.bytes), (2, 30));
+        assert_eq!(s.buckets[0], 1);
+        assert_eq!(s.buckets[4], 1);
+
+        let text = m.render_prometheus();
+        assert!(text.contains("http_client_requests_total{host=\"a.com\",status=\"200\"} 2"));
+        assert!(text.contains(
+            "http_client_request_duration_seconds_bucket{host=\"a.com\",status=\"200\",le=\"0.05\"} 1"
+        ));
+        assert!(text.contains(
+            "http_client_request_duration_seconds_bucket{host=\"a.com\",status=\"timeout\",le=\"+Inf\"} 1"
+        ));
+    }
+}
@@ -0,0 +1,270 @@
+//! 请求中间件：在发送前、收到响应后插入通用逻辑（鉴权、日志、统计、缓存、mock 等）
+
+use std::fmt;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use reqwest::{Method, Response, StatusCode};
+use tracing::{info, warn};
+
+use super::{ClientKey, ReqError};
+
+static GLOBAL: Lazy<RwLock<MiddlewareStack>> = Lazy::new(|| RwLock::new(default_stack()));
+
+/// 发送前的请求内容，中间件可以修改
+#[derive(Debug, Clone)]
+pub struct ReqContext {
+    pub method: Method,
+    /// 包含查询参数的完整 url
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<Vec<u8>>,
+    pub proxy: Option<String>,
+}
+
+impl ReqContext {
+    /// 用于日志的请求头
+    pub fn head_lines(&self) -> Vec<String> {
+        self.headers
+            .iter()
+            .map(|(k, v)| format!("{}: {}", k, v))
+            .collect()
+    }
+
+    /// 用于日志的请求头，鉴权相关的值替换为 `***`
+    pub fn redacted_head_lines(&self) -> Vec<String> {
+        self.headers
+            .iter()
+            .map(|(k, v)| match is_sensitive_header(k) {
+                true => format!("{}: ***", k),
+                false => format!("{}: {}", k, v),
+            })
+            .collect()
+    }
+}
+
+// 请求头名称包含这些词时视为鉴权信息
+const SENSITIVE_HEADER_WORDS: &[&str] = &[
+    "auth",
+    "key",
+    "sign",
+    "passphrase",
+    "secret",
+    "token",
+    "cookie",
+    "session",
+    "password",
+];
+
+/// 是否为鉴权相关的请求头，例如 Authorization、X-MBX-APIKEY、OK-ACCESS-SIGN
+pub fn is_sensitive_header(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    SENSITIVE_HEADER_WORDS.iter().any(|w| name.contains(w))
+}
+
+/// 中间件，所有方法都有默认的空实现
+pub trait Middleware: Send + Sync {
+    /// 发送前调用；返回 Some 时不再发送请求，直接使用该响应
+    fn before_send(&self, _ctx: &mut ReqContext) -> Result<Option<Response>, ReqError> {
+        Ok(None)
+    }
+
+    /// 收到响应或发送失败后调用，可以替换结果
+    fn after_receive(&self, _ctx: &ReqContext, _resp: &mut Result<Response, ReqError>) {}
+
+    /// 请求最终失败时调用（包括非成功状态码）
+    fn on_error(&self, _ctx: &ReqContext, _err: &ReqError) {}
+
+    /// 新建 Client 时调用
+    fn on_client_created(&self, _key: &ClientKey) {}
+}
+
+/// 中间件列表，按加入顺序依次调用
+#[derive(Clone, Default)]
+pub struct MiddlewareStack(Vec<Arc<dyn Middleware>>);
+
+impl MiddlewareStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, m: impl Middleware + 'static) -> Self {
+        self.0.push(Arc::new(m));
+        self
+    }
+
+    pub fn push(&mut self, m: Arc<dyn Middleware>) {
+        self.0.push(m);
+    }
+
+    pub fn extend(&mut self, other: &MiddlewareStack) {
+        self.0.extend(other.0.iter().cloned());
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn before_send(&self, ctx: &mut ReqContext) -> Result<Option<Response>, ReqError> {
+        for m in self.0.iter() {
+            if let Some(resp) = m.before_send(ctx)? {
+                return Ok(Some(resp));
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn after_receive(&self, ctx: &ReqContext, resp: &mut Result<Response, ReqError>) {
+        for m in self.0.iter() {
+            m.after_receive(ctx, resp);
+        }
+    }
+
+    pub fn on_error(&self, ctx: &ReqContext, err: &ReqError) {
+        for m in self.0.iter() {
+            m.on_error(ctx, err);
+        }
+    }
+
+    pub fn on_client_created(&self, key: &ClientKey) {
+        for m in self.0.iter() {
+            m.on_client_created(key);
+        }
+    }
+}
+
+impl fmt::Debug for MiddlewareStack {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MiddlewareStack({})", self.0.len())
+    }
+}
+
+/// 默认的全局中间件：新建 Client 日志、错误日志
+pub fn default_stack() -> MiddlewareStack {
+    MiddlewareStack::new().with(ClientLog).with(ErrorLog)
+}
+
+pub fn global_middlewares() -> MiddlewareStack {
+    GLOBAL.read().clone()
+}
+
+/// 替换全局中间件，传入空列表可关闭默认日志
+pub fn set_global_middlewares(stack: MiddlewareStack) {
+    *GLOBAL.write() = stack;
+}
+
+pub fn push_global_middleware(m: impl Middleware + 'static) {
+    GLOBAL.write().push(Arc::new(m));
+}
+
+/// 构造响应，用于在 before_send 中直接返回
+pub fn mock_response(status: StatusCode, body: impl Into<Vec<u8>>) -> Response {
+    let resp = http::Response::builder()
+        .status(status)
+        .body(body.into())
+        .expect("valid status");
+    Response::from(resp)
+}
+
+/// 新建 Client 时记录日志
+pub struct ClientLog;
+
+impl Middleware for ClientLog {
+    fn on_client_created(&self, key: &ClientKey) {
+        info!(proxy = ?key.proxy, "创建 proxy: {:?} 的cli", key.proxy);
+    }
+}
+
+/// 请求失败时记录带请求头的错误信息，鉴权相关的请求头只记录名称
+pub struct ErrorLog;
+
+impl ErrorLog {
+    fn line(ctx: &ReqContext, err: &ReqError) -> String {
+        format_err(&ctx.url, &ctx.redacted_head_lines(), err)
+    }
+}
+
+impl Middleware for ErrorLog {
+    fn on_error(&self, ctx: &ReqContext, err: &ReqError) {
+        warn!("{}", Self::line(ctx, err));
+    }
+}
+
+// 格式化错误信息，带上请求头方便排查
+fn format_err(ur: &str, head: &[String], err: &ReqError) -> String {
+    format!(
+        r#"请求url：{}
+    请求头 :
+{}
+    错误信息 {}
+                "#,
+        ur,
+        head.join("\n"),
+        err
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tool::req::RequestSpec;
+
+    // 带上鉴权头后直接返回固定响应
+    struct Auth;
+
+    impl Middleware for Auth {
+        fn before_send(&self, ctx: &mut ReqContext) -> Result<Option<Response>, ReqError> {
+            ctx.headers.push(("X-Token".to_string(), "t".to_string()));
+            Ok(None)
+        }
+    }
+
+    struct Mock;
+
+    impl Middleware for Mock {
+        fn before_send(&self, ctx: &mut ReqContext) -> Result<Option<Response>, ReqError> {
+            let token = ctx.head_lines().join(",");
+            Ok(Some(mock_response(StatusCode::OK, token)))
+        }
+    }
+
+    #[tokio::test]
+    async fn short_circuit() {
+        let resp = RequestSpec::get("http://mock.invalid/a")
+            .middlewares(&MiddlewareStack::new().with(Auth).with(Mock))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.text(), "X-Token: t");
+    }
+
+    #[test]
+    fn error_log_redacts_secrets() {
+        let ctx = ReqContext {
+            method: Method::GET,
+            url: "http://mock.invalid/a".to_string(),
+            headers: vec![
+                ("X-MBX-APIKEY".to_string(), "k-secret".to_string()),
+                ("OK-ACCESS-SIGN".to_string(), "s-secret".to_string()),
+                ("OK-ACCESS-PASSPHRASE".to_string(), "p-secret".to_string()),
+                ("Authorization".to_string(), "Bearer b-secret".to_string()),
+                ("Content-Type".to_string(), "application/json".to_string()),
+            ],
+            body: None,
+            proxy: None,
+        };
+        let err = ReqError::Timeout {
+            url: ctx.url.clone(),
+        };
+        let line = ErrorLog::line(&ctx, &err);
+        assert!(!line.contains("secret"));
+        assert!(line.contains("X-MBX-APIKEY: ***"));
+        assert!(line.contains("OK-ACCESS-PASSPHRASE: ***"));
+        assert!(line.contains("Content-Type: application/json"));
+    }
+}
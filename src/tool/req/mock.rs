@@ -0,0 +1,280 @@
+//! 内存中的 Transport，用于测试依赖 tool::req 的代码，不需要真实的服务
+//!
+//! ```ignore
+//! let mock = Arc::new(
+//!     MockTransport::new()
+//!         .get("http://list/black", "a,b")
+//!         .fail_times("http://list/black", 1, ReqError::Connect { url: "".into(), msg: "down".into() })
+//!         .delay("http://list/white", Duration::from_millis(50)),
+//! );
+//! with_transport(mock.clone(), async { /* 调用被测代码 */ }).await;
+//! assert_eq!(mock.hits("http://list/black"), 2);
+//! ```
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use reqwest::{Method, Response, StatusCode};
+
+use super::{ReqContext, ReqError, Transport, TransportFuture, TransportRequest};
+
+/// 模拟的响应或错误
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    Response {
+        status: StatusCode,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    },
+    Error(ReqError),
+}
+
+impl MockReply {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Self::status(StatusCode::OK, body)
+    }
+
+    pub fn status(status: StatusCode, body: impl Into<Vec<u8>>) -> Self {
+        MockReply::Response {
+            status,
+            headers: Vec::new(),
+            body: body.into(),
+        }
+    }
+
+    pub fn error(err: ReqError) -> Self {
+        MockReply::Error(err)
+    }
+
+    /// 添加响应头，对错误无效
+    pub fn header(mut self, k: &str, v: &str) -> Self {
+        if let MockReply::Response { headers, .. } = &mut self {
+            headers.push((k.to_string(), v.to_string()));
+        }
+        self
+    }
+
+    fn into_result(self) -> Result<Response, ReqError> {
+        let (status, headers, body) = match self {
+            MockReply::Response {
+                status,
+                headers,
+                body,
+            } => (status, headers, body),
+            MockReply::Error(err) => return Err(err),
+        };
+        let mut resp = http::Response::builder().status(status);
+        for (k, v) in headers {
+            resp = resp.header(k, v);
+        }
+        Ok(Response::from(
+            resp.body(body).expect("valid mock response"),
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct MockRoute {
+    url: String,
+    replies: Vec<(Method, MockReply)>,
+    // 优先返回，用完后使用 replies
+    script: VecDeque<MockReply>,
+    delay: Duration,
+}
+
+impl MockRoute {
+    fn reply(&mut self, method: &Method) -> MockReply {
+        if let Some(r) = self.script.pop_front() {
+            return r;
+        }
+        self.replies
+            .iter()
+            .find(|(m, _)| m == method)
+            .map(|(_, r)| r.clone())
+            .unwrap_or_else(not_found)
+    }
+}
+
+fn not_found() -> MockReply {
+    MockReply::status(StatusCode::NOT_FOUND, "")
+}
+
+/// 按 url 返回预先配置的响应，并记录收到的请求
+///
+/// url 不带查询参数时匹配任意查询参数；未配置的 url 返回 404
+#[derive(Debug, Default)]
+pub struct MockTransport {
+    routes: Mutex<Vec<MockRoute>>,
+    requests: Mutex<Vec<ReqContext>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 配置 method + url 的响应
+    pub fn on(self, method: Method, url: &str, reply: MockReply) -> Self {
+        self.with_route(url, |r| {
+            r.replies.retain(|(m, _)| *m != method);
+            r.replies.push((method, reply));
+        })
+    }
+
+    /// GET 请求返回 200 和 body
+    pub fn get(self, url: &str, body: impl Into<Vec<u8>>) -> Self {
+        self.on(Method::GET, url, MockReply::ok(body))
+    }
+
+    /// 接下来的 times 次请求（不区分 method）返回 err，之后恢复为配置的响应
+    pub fn fail_times(self, url: &str, times: usize, err: ReqError) -> Self {
+        self.script(url, vec![MockReply::Error(err); times])
+    }
+
+    /// 接下来的请求依次返回 replies，用完后恢复为配置的响应
+    pub fn script(self, url: &str, replies: impl IntoIterator<Item = MockReply>) -> Self {
+        self.with_route(url, |r| r.script.extend(replies))
+    }
+
+    /// 响应前等待，超过请求的超时时间时返回超时错误
+    pub fn delay(self, url: &str, delay: Duration) -> Self {
+        self.with_route(url, |r| r.delay = delay)
+    }
+
+    /// 收到的所有请求，按时间顺序
+    pub fn requests(&self) -> Vec<ReqContext> {
+        self.requests.lock().clone()
+    }
+
+    /// 匹配 url 的请求次数
+    pub fn hits(&self, url: &str) -> usize {
+        self.requests
+            .lock()
+            .iter()
+            .filter(|c| url_matches(url, &c.url))
+            .count()
+    }
+
+    fn with_route(self, url: &str, f: impl FnOnce(&mut MockRoute)) -> Self {
+        {
+            let mut routes = self.routes.lock();
+            let i = match routes.iter().position(|r| r.url == url) {
+                Some(i) => i,
+                None => {
+                    routes.push(MockRoute {
+                        url: url.to_string(),
+                        replies: Vec::new(),
+                        script: VecDeque::new(),
+                        delay: Duration::ZERO,
+                    });
+                    routes.len() - 1
+                }
+            };
+            f(&mut routes[i]);
+        }
+        self
+    }
+
+    fn pick(&self, method: &Method, url: &str) -> (MockReply, Duration) {
+        let mut routes = self.routes.lock();
+        // 完全相同的 url 优先
+        let i = routes
+            .iter()
+            .position(|r| r.url == url)
+            .or_else(|| routes.iter().position(|r| url_matches(&r.url, url)));
+        match i {
+            Some(i) => (routes[i].reply(method), routes[i].delay),
+            None => (not_found(), Duration::ZERO),
+        }
+    }
+}
+
+impl Transport for MockTransport {
+    fn send<'a>(&'a self, req: TransportRequest<'a>) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let ctx = req.ctx;
+            self.requests.lock().push(ctx.clone());
+            let (reply, delay) = self.pick(&ctx.method, &ctx.url);
+            if !delay.is_zero() {
+                if delay >= req.key.timeout {
+                    tokio::time::sleep(req.key.timeout).await;
+                    return Err(ReqError::Timeout {
+                        url: ctx.url.clone(),
+                    });
+                }
+                tokio::time::sleep(delay).await;
+            }
+            reply.into_result()
+        })
+    }
+}
+
+// 配置的 url 不带查询参数时，忽略请求的查询参数
+fn url_matches(route: &str, url: &str) -> bool {
+    route == url || (!route.contains('?') && url.split('?').next() == Some(route))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::tool::req::{get_with_timeout, with_transport, RequestSpec};
+
+    const URL: &str = "http://mock.invalid/list";
+
+    #[tokio::test]
+    async fn scripted_replies() {
+        let mock = Arc::new(
+            MockTransport::new()
+                .get(URL, "a,b")
+                .on(
+                    Method::POST,
+                    URL,
+                    MockReply::status(StatusCode::CREATED, ""),
+                )
+                .fail_times(
+                    URL,
+                    1,
+                    ReqError::Connect {
+                        url: URL.to_string(),
+                        msg: "down".to_string(),
+                    },
+                ),
+        );
+        let rs = with_transport(mock.clone(), async {
+            let first = get_with_timeout(URL, None, &None, Duration::from_secs(1)).await;
+            let second = RequestSpec::get(URL).query("t", "1").send().await;
+            let post = RequestSpec::post(URL).send().await;
+            let missing = RequestSpec::get("http://mock.invalid/none").send().await;
+            (first, second, post, missing)
+        })
+        .await;
+
+        assert!(matches!(rs.0, Err(ReqError::Connect { .. })));
+        assert_eq!(rs.1.unwrap().text(), "a,b");
+        assert_eq!(rs.2.unwrap().status, StatusCode::CREATED);
+        assert_eq!(rs.3.unwrap_err().status(), Some(StatusCode::NOT_FOUND));
+        assert_eq!(mock.hits(URL), 3);
+        assert_eq!(mock.requests()[1].url, format!("{}?t=1", URL));
+    }
+
+    #[tokio::test]
+    async fn delay_and_timeout() {
+        let mock = Arc::new(
+            MockTransport::new()
+                .get(URL, "ok")
+                .delay(URL, Duration::from_millis(50)),
+        );
+        let slow = RequestSpec::get(URL)
+            .transport(mock.clone())
+            .timeout(Duration::from_millis(20))
+            .send()
+            .await;
+        assert!(matches!(slow, Err(ReqError::Timeout { .. })));
+
+        let rs = RequestSpec::get(URL).transport(mock).send().await.unwrap();
+        assert_eq!(rs.text(), "ok");
+    }
+}
@@ -0,0 +1,296 @@
+//! 封装常用请求代码
+
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::redirect::Policy;
+use reqwest::{Client, ClientBuilder, Method, Response, StatusCode};
+
+use crate::tool::tls::{default_tls_options, TlsOptions};
+
+mod cache;
+mod clients;
+mod download;
+mod error;
+mod hedge;
+mod limit;
+mod metrics;
+mod middleware;
+mod mock;
+mod options;
+mod proxy;
+mod proxy_pool;
+mod resolve;
+mod response;
+mod retry;
+mod sign;
+mod spec;
+mod transport;
+mod typed;
+
+pub use cache::*;
+pub use clients::*;
+pub use download::*;
+pub use error::*;
+pub use hedge::*;
+pub use limit::*;
+pub use metrics::*;
+pub use middleware::*;
+pub use mock::*;
+pub use options::*;
+pub use proxy::*;
+pub use proxy_pool::*;
+pub use resolve::*;
+pub use response::*;
+pub use retry::*;
+pub use sign::*;
+pub use spec::*;
+pub use transport::*;
+pub use typed::*;
+
+use spec::check_resp;
+
+/// 按配置创建 Client，配置错误时返回错误而不是 panic
+pub fn try_build_client(key: &ClientKey) -> Result<Client, ReqError> {
+    let mut cli =
+        builder_with(&key.proxy, key.timeout, &key.options, &key.tls)?.redirect(Policy::default());
+    if let Some((h, addr)) = &key.resolve {
+        let addr = addr.parse::<SocketAddr>().map_err(|err| ReqError::Build {
+            url: h.clone(),
+            msg: format!("dns 地址 {} 格式错误：{}", addr, err),
+        })?;
+        cli = cli.resolve(h, addr)
+    }
+    if let Some(r) = &key.resolver {
+        cli = cli.dns_resolver(r.0.clone());
+    }
+
+    cli.build().map_err(|err| ReqError::Build {
+        url: String::new(),
+        msg: format!("创建 client 失败：{}", err),
+    })
+}
+
+pub async fn get(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+) -> Result<String, ReqError> {
+    get_with_timeout(url, req_head, proxy, DEFAULT_TIMEOUT).await
+}
+pub async fn get_with_timeout(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    timeout: Duration,
+) -> Result<String, ReqError> {
+    spec_of(Method::GET, url, req_head, proxy, timeout)
+        .send()
+        .await
+        .map(ReqResponse::into_text)
+}
+
+pub async fn exec_get(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    timeout: Duration,
+) -> Result<(StatusCode, String)> {
+    let resp = spec_of(Method::GET, url, req_head, proxy, timeout)
+        .execute()
+        .await?;
+    let status = resp.status();
+    let text = resp.text().await?;
+    Ok((status, text))
+}
+
+pub async fn post(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    body: String,
+    timeout: Duration,
+) -> Result<String, ReqError> {
+    spec_of(Method::POST, url, req_head, proxy, timeout)
+        .body(body)
+        .send()
+        .await
+        .map(ReqResponse::into_text)
+}
+
+/// 保留的旧接口，新代码请使用 [`RequestSpec`]
+pub async fn exec_req(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    method: Method,
+    body: Option<String>,
+    force_newcli: bool, // 是否强制使用新的cli
+    dns: Option<&str>,
+    timeout: Duration,
+) -> Result<Response, ReqError> {
+    let mut spec = spec_of(method, url, req_head, proxy, timeout).force_new_client(force_newcli);
+    if let Some(addr) = dns {
+        spec = spec.dns(addr);
+    }
+    if let Some(d) = body {
+        spec = spec.body(d);
+    }
+    spec.execute().await
+}
+
+fn spec_of(
+    method: Method,
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    timeout: Duration,
+) -> RequestSpec {
+    let mut spec = RequestSpec::new(method, url).proxy(proxy).timeout(timeout);
+    if let Some(req_heads) = req_head {
+        // 添加默认的请求头参数
+        spec = spec.headers(req_heads.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+    }
+    spec
+}
+
+/// 读取响应，2xx 返回内容，否则返回状态码错误
+pub async fn handler_resp(
+    rs_resp: Result<Response, ReqError>,
+    ur: &str,
+    head: &Vec<String>,
+) -> Result<String, ReqError> {
+    handler_resp_with(rs_resp, ur, head, &SuccessPolicy::default()).await
+}
+
+/// 与 handler_resp 相同，按 success 判断状态码是否成功
+pub async fn handler_resp_with(
+    rs_resp: Result<Response, ReqError>,
+    ur: &str,
+    head: &[String],
+    success: &SuccessPolicy,
+) -> Result<String, ReqError> {
+    let ctx = ReqContext {
+        method: Method::GET,
+        url: ur.to_string(),
+        headers: head
+            .iter()
+            .map(|h| match h.split_once(": ") {
+                Some((k, v)) => (k.to_string(), v.to_string()),
+                None => (h.clone(), String::new()),
+            })
+            .collect(),
+        body: None,
+        proxy: None,
+    };
+    let resp = match rs_resp {
+        Ok(resp) => ReqResponse::read(resp, ur).await,
+        Err(err) => Err(err),
+    };
+    check_resp(resp, &ctx, &global_middlewares(), success).map(ReqResponse::into_text)
+}
+
+/// 生成 ClientBuilder，使用全局默认的 TLS 配置（默认校验证书）
+///
+/// 代理配置错误时 panic，需要处理错误时使用 [`try_gen_client_builder`]
+pub fn gen_client_builder(proxy: &Option<String>, timeout: Duration) -> ClientBuilder {
+    gen_client_builder_with(proxy, timeout, &ClientOptions::default())
+}
+
+/// 生成 ClientBuilder，使用指定的连接参数
+pub fn gen_client_builder_with(
+    proxy: &Option<String>,
+    timeout: Duration,
+    options: &ClientOptions,
+) -> ClientBuilder {
+    try_gen_client_builder_with(proxy, timeout, options).unwrap_or_else(|err| panic!("{}", err))
+}
+
+/// 生成 ClientBuilder，校验代理配置
+pub fn try_gen_client_builder(
+    proxy: &Option<String>,
+    timeout: Duration,
+) -> Result<ClientBuilder, ReqError> {
+    try_gen_client_builder_with(proxy, timeout, &ClientOptions::default())
+}
+
+pub fn try_gen_client_builder_with(
+    proxy: &Option<String>,
+    timeout: Duration,
+    options: &ClientOptions,
+) -> Result<ClientBuilder, ReqError> {
+    builder_with(proxy, timeout, options, &default_tls_options())
+}
+
+fn builder_with(
+    proxy: &Option<String>,
+    timeout: Duration,
+    options: &ClientOptions,
+    tls: &TlsOptions,
+) -> Result<ClientBuilder, ReqError> {
+    let mut cli = options.apply(ClientBuilder::new());
+    // 默认配置直接使用 reqwest 自带的 rustls 配置
+    if !tls.is_default() {
+        let config = tls.http_client_config().map_err(|err| ReqError::Build {
+            url: String::new(),
+            msg: err.to_string(),
+        })?;
+        cli = cli.use_preconfigured_tls(config);
+    }
+    try_with_proxy(cli, proxy, timeout)
+}
+
+/// 加载并配置代理
+fn try_with_proxy(
+    mut cli: ClientBuilder,
+    proxy: &Option<String>,
+    timeout: Duration,
+) -> Result<ClientBuilder, ReqError> {
+    cli = cli.timeout(timeout);
+    let proxy = match proxy {
+        Some(proxy) => proxy,
+        None => {
+            return match env::var("HTTP_PROXY") {
+                Ok(p) => {
+                    let proxy = reqwest::Proxy::https(&p).map_err(|err| ReqError::Proxy {
+                        proxy: p,
+                        msg: format!("HTTP_PROXY 格式错误：{}", err),
+                    })?;
+                    Ok(cli.proxy(proxy))
+                }
+                Err(_) => Ok(cli),
+            }
+        }
+    };
+
+    let target = ProxyTarget::parse(proxy)?;
+    let proxy_url = match (&target, target.addr_url()) {
+        (ProxyTarget::Local(ip), _) => return Ok(cli.local_address(*ip)),
+        (_, Some(u)) => u,
+        (_, None) => return Ok(cli),
+    };
+
+    let mut p = reqwest::Proxy::all(&proxy_url).map_err(|err| ReqError::Proxy {
+        proxy: proxy.clone(),
+        msg: err.to_string(),
+    })?;
+    if let ProxyTarget::Remote {
+        auth: Some((u, pass)),
+        ..
+    } = &target
+    {
+        p = p.basic_auth(u, pass);
+    }
+
+    Ok(cli.proxy(p))
+}
+
+// url 解码
+pub fn url_info_decode(text: &str) -> String {
+    urlencoding::decode(text)
+        .map(|decoded| decoded.to_string())
+        .unwrap_or_else(|_| text.to_string())
+}
@@ -0,0 +1,167 @@
+//! Client 连接参数
+//!
+//! ```toml
+//! pool_idle_timeout = "90s"   # "none" 表示不回收
+//! pool_max_idle_per_host = 4
+//! tcp_keepalive = "30s"
+//! tcp_nodelay = true
+//! http2_prior_knowledge = false
+//! connect_timeout = "3s"
+//! brotli = true
+//! ```
+
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::ClientBuilder;
+use serde::{Deserialize, Deserializer};
+
+use crate::tool::config::load_toml;
+use crate::tool::deserialize_opt_duration;
+
+/// 连接池、TCP、HTTP/2 和压缩相关配置，未填写的字段使用默认值
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct ClientOptions {
+    /// 空闲连接保留时间，默认 90s；None 表示不回收，配置文件中写 "none"
+    #[serde(deserialize_with = "deserialize_idle_timeout")]
+    pub pool_idle_timeout: Option<Duration>,
+    /// 每个域名最多保留的空闲连接数，None 表示不限制
+    pub pool_max_idle_per_host: Option<usize>,
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub tcp_keepalive: Option<Duration>,
+    pub tcp_nodelay: bool,
+    /// 直接使用 HTTP/2，不经过协商，只适用于确定支持 h2 的服务
+    pub http2_prior_knowledge: bool,
+    /// 建立连接的超时时间，与整个请求的超时时间分开
+    #[serde(deserialize_with = "deserialize_opt_duration")]
+    pub connect_timeout: Option<Duration>,
+    pub gzip: bool,
+    pub brotli: bool,
+    pub deflate: bool,
+}
+
+// 与 deserialize_opt_duration 相同，另外支持 "none" 表示不回收
+fn deserialize_idle_timeout<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    use serde::de::Error;
+    let value = String::deserialize(deserializer)?;
+    if value.trim().eq_ignore_ascii_case("none") {
+        return Ok(None);
+    }
+    humantime::parse_duration(value.trim())
+        .map(Some)
+        .map_err(|err| D::Error::custom(err.to_string()))
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            pool_idle_timeout: Some(Duration::from_secs(90)),
+            pool_max_idle_per_host: None,
+            tcp_keepalive: None,
+            tcp_nodelay: true,
+            http2_prior_knowledge: false,
+            connect_timeout: None,
+            gzip: true,
+            brotli: false,
+            deflate: false,
+        }
+    }
+}
+
+impl ClientOptions {
+    pub fn from_toml(content: &str) -> Result<Self> {
+        load_toml(content)
+    }
+
+    pub fn pool_idle_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    pub fn tcp_keepalive(mut self, interval: Option<Duration>) -> Self {
+        self.tcp_keepalive = interval;
+        self
+    }
+
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
+
+    pub fn http2_prior_knowledge(mut self, enable: bool) -> Self {
+        self.http2_prior_knowledge = enable;
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// 设置支持的压缩格式
+    pub fn compression(mut self, gzip: bool, brotli: bool, deflate: bool) -> Self {
+        self.gzip = gzip;
+        self.brotli = brotli;
+        self.deflate = deflate;
+        self
+    }
+
+    /// 应用到 ClientBuilder
+    pub fn apply(&self, mut cli: ClientBuilder) -> ClientBuilder {
+        cli = cli
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(self.tcp_keepalive)
+            .tcp_nodelay(self.tcp_nodelay)
+            .gzip(self.gzip)
+            .brotli(self.brotli)
+            .deflate(self.deflate);
+        if let Some(max) = self.pool_max_idle_per_host {
+            cli = cli.pool_max_idle_per_host(max);
+        }
+        if let Some(timeout) = self.connect_timeout {
+            cli = cli.connect_timeout(timeout);
+        }
+        if self.http2_prior_knowledge {
+            cli = cli.http2_prior_knowledge();
+        }
+        cli
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_toml() {
+        let o = ClientOptions::from_toml(
+            r#"
+            poolMaxIdlePerHost = 4
+            tcp_keepalive = "30s"
+            connect-timeout = "1500ms"
+            brotli = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(o.pool_max_idle_per_host, Some(4));
+        assert_eq!(o.tcp_keepalive, Some(Duration::from_secs(30)));
+        assert_eq!(o.connect_timeout, Some(Duration::from_millis(1500)));
+        assert!(o.brotli && o.gzip && !o.deflate);
+        assert_eq!(o.pool_idle_timeout, Some(Duration::from_secs(90)));
+
+        let o = ClientOptions::from_toml(r#"pool_idle_timeout = "none""#).unwrap();
+        assert_eq!(o.pool_idle_timeout, None);
+        let o = ClientOptions::from_toml(r#"pool_idle_timeout = "10s""#).unwrap();
+        assert_eq!(o.pool_idle_timeout, Some(Duration::from_secs(10)));
+        assert!(ClientOptions::from_toml(r#"pool_idle_timeout = "x""#).is_err());
+    }
+}
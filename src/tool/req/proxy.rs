@@ -0,0 +1,117 @@
+//! 解析并校验代理配置
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use reqwest::Url;
+
+use super::{url_info_decode, ReqError};
+
+/// 解析后的代理配置
+///
+/// 支持的格式：
+/// - `local://<ip>`：绑定本地出口 ip
+/// - `socks5://`、`socks5h://`、`http://`、`https://`，可带 `user:pass@`，端口可省略
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyTarget {
+    Local(IpAddr),
+    Remote {
+        scheme: String,
+        host: String,
+        port: u16,
+        /// 已做 url 解码的用户名、密码
+        auth: Option<(String, String)>,
+    },
+}
+
+impl ProxyTarget {
+    pub fn parse(proxy: &str) -> Result<Self, ReqError> {
+        let err = |msg: String| ReqError::Proxy {
+            proxy: proxy.to_string(),
+            msg,
+        };
+
+        if proxy.starts_with("local") {
+            let ip = proxy.trim_start_matches("local://");
+            return IpAddr::from_str(ip)
+                .map(ProxyTarget::Local)
+                .map_err(|e| err(format!("本地地址 {} 格式错误：{}", ip, e)));
+        }
+
+        let url = Url::parse(proxy).map_err(|e| err(format!("代理地址格式错误：{}", e)))?;
+        let scheme = url.scheme().to_string();
+        let default_port = match scheme.as_str() {
+            "socks5" | "socks5h" => 1080,
+            "http" => 80,
+            "https" => 443,
+            s => return Err(err(format!("不支持的代理协议：{}", s))),
+        };
+        let host = url
+            .host()
+            .map(|h| h.to_string())
+            .ok_or_else(|| err("缺少代理主机".to_string()))?;
+        let port = url.port().unwrap_or(default_port);
+
+        let auth = (url.has_authority() && !url.username().is_empty()).then(|| {
+            (
+                url_info_decode(url.username()),
+                url_info_decode(url.password().unwrap_or_default()),
+            )
+        });
+
+        Ok(ProxyTarget::Remote {
+            scheme,
+            host,
+            port,
+            auth,
+        })
+    }
+
+    /// 不带认证信息的代理地址，例如 socks5://1.2.3.4:1080
+    pub fn addr_url(&self) -> Option<String> {
+        match self {
+            ProxyTarget::Local(_) => None,
+            ProxyTarget::Remote {
+                scheme, host, port, ..
+            } => Some(format!("{}://{}:{}", scheme, host, port)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_proxy() {
+        assert_eq!(
+            ProxyTarget::parse("local://10.0.0.2").unwrap(),
+            ProxyTarget::Local("10.0.0.2".parse().unwrap())
+        );
+        assert_eq!(
+            ProxyTarget::parse("socks5://u%40x:p@1.2.3.4").unwrap(),
+            ProxyTarget::Remote {
+                scheme: "socks5".to_string(),
+                host: "1.2.3.4".to_string(),
+                port: 1080,
+                auth: Some(("u@x".to_string(), "p".to_string())),
+            }
+        );
+        assert_eq!(
+            ProxyTarget::parse("http://proxy.local")
+                .unwrap()
+                .addr_url()
+                .unwrap(),
+            "http://proxy.local:80"
+        );
+
+        for bad in [
+            "local://10.0.0",
+            "ftp://1.2.3.4:21",
+            "socks5:1.2.3.4",
+            "1.2.3.4:1080",
+        ] {
+            assert!(ProxyTarget::parse(bad).is_err(), "{}", bad);
+        }
+    }
+}
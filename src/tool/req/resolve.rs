@@ -0,0 +1,256 @@
+//! 自定义域名解析：hosts 风格的多域名、多 ip 配置，未配置的域名使用系统 dns
+//!
+//! ```ignore
+//! let txt = hosts::load_host_file("./hosts").await?;
+//! let resolver = Arc::new(HostsResolver::from_hosts_text(&txt, AddrOrder::RoundRobin));
+//! RequestSpec::get(url).resolver(resolver.clone()).send().await?;
+//! ```
+
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures_util::future::join_all;
+use hyper::client::connect::dns::Name;
+use parking_lot::{Mutex, RwLock};
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use tokio::net::TcpStream;
+
+/// 多个 ip 的使用顺序
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddrOrder {
+    /// 每次解析轮换第一个 ip
+    RoundRobin,
+    /// 按测得的连接耗时从快到慢，未测过的排在最后
+    FastestFirst,
+}
+
+pub struct HostsResolver {
+    order: AddrOrder,
+    /// 未配置的域名是否使用系统 dns
+    system_fallback: bool,
+    hosts: RwLock<HashMap<String, Vec<IpAddr>>>,
+    latency: Mutex<HashMap<IpAddr, Duration>>,
+    cursor: AtomicUsize,
+}
+
+impl HostsResolver {
+    pub fn new(order: AddrOrder) -> Self {
+        Self {
+            order,
+            system_fallback: true,
+            hosts: RwLock::new(HashMap::new()),
+            latency: Mutex::new(HashMap::new()),
+            cursor: AtomicUsize::new(0),
+        }
+    }
+
+    /// 解析 hosts 文件内容：每行 `ip 域名1 域名2 ...`，# 之后为注释
+    pub fn from_hosts_text(text: &str, order: AddrOrder) -> Self {
+        let r = Self::new(order);
+        r.load_hosts_text(text);
+        r
+    }
+
+    pub fn system_fallback(mut self, enable: bool) -> Self {
+        self.system_fallback = enable;
+        self
+    }
+
+    /// 追加 hosts 文件内容，同一域名可以出现在多行
+    pub fn load_hosts_text(&self, text: &str) {
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default();
+            let mut parts = line.split_ascii_whitespace();
+            let ip = match parts.next().and_then(parse_ip) {
+                Some(ip) => ip,
+                None => continue,
+            };
+            for host in parts {
+                self.insert(host, [ip]);
+            }
+        }
+    }
+
+    /// 为域名追加 ip，可以直接使用 `hosts::split_txt` 的结果；无法解析的项会被忽略
+    pub fn add_addrs<S: AsRef<str>>(&self, host: &str, addrs: impl IntoIterator<Item = S>) {
+        self.insert(host, addrs.into_iter().filter_map(|a| parse_ip(a.as_ref())));
+    }
+
+    pub fn insert(&self, host: &str, ips: impl IntoIterator<Item = IpAddr>) {
+        let mut hosts = self.hosts.write();
+        let list = hosts.entry(host.to_ascii_lowercase()).or_default();
+        for ip in ips {
+            if !list.contains(&ip) {
+                list.push(ip);
+            }
+        }
+    }
+
+    /// 替换域名的全部 ip
+    pub fn set(&self, host: &str, ips: Vec<IpAddr>) {
+        self.hosts.write().insert(host.to_ascii_lowercase(), ips);
+    }
+
+    pub fn remove(&self, host: &str) {
+        self.hosts.write().remove(&host.to_ascii_lowercase());
+    }
+
+    pub fn hosts(&self) -> Vec<String> {
+        self.hosts.read().keys().cloned().collect()
+    }
+
+    /// 按配置的顺序返回域名的 ip，未配置时返回空
+    pub fn lookup(&self, host: &str) -> Vec<IpAddr> {
+        let mut ips = match self.hosts.read().get(&host.to_ascii_lowercase()) {
+            Some(ips) if !ips.is_empty() => ips.clone(),
+            _ => return Vec::new(),
+        };
+        match self.order {
+            AddrOrder::RoundRobin => {
+                let n = self.cursor.fetch_add(1, Ordering::Relaxed) % ips.len();
+                ips.rotate_left(n);
+            }
+            AddrOrder::FastestFirst => {
+                let latency = self.latency.lock();
+                ips.sort_by_key(|ip| latency.get(ip).copied().unwrap_or(Duration::MAX));
+            }
+        }
+        ips
+    }
+
+    /// 记录 ip 的连接耗时，用于 FastestFirst
+    pub fn report_latency(&self, ip: IpAddr, latency: Duration) {
+        self.latency.lock().insert(ip, latency);
+    }
+
+    pub fn latency_of(&self, ip: &IpAddr) -> Option<Duration> {
+        self.latency.lock().get(ip).copied()
+    }
+
+    /// 并发测量所有配置的 ip 的 tcp 连接耗时，连接失败的记为最慢
+    pub async fn measure(&self, port: u16, timeout: Duration) {
+        let mut ips = self
+            .hosts
+            .read()
+            .values()
+            .flatten()
+            .copied()
+            .collect::<Vec<_>>();
+        ips.sort();
+        ips.dedup();
+        let latencies = join_all(ips.iter().map(|ip| async move {
+            let start = Instant::now();
+            let rs = tokio::time::timeout(timeout, TcpStream::connect(SocketAddr::new(*ip, port)));
+            match rs.await {
+                Ok(Ok(_)) => start.elapsed(),
+                _ => Duration::MAX,
+            }
+        }))
+        .await;
+        for (ip, latency) in ips.into_iter().zip(latencies) {
+            self.report_latency(ip, latency);
+        }
+    }
+}
+
+impl fmt::Debug for HostsResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HostsResolver")
+            .field("order", &self.order)
+            .field("system_fallback", &self.system_fallback)
+            .field("hosts", &*self.hosts.read())
+            .finish()
+    }
+}
+
+impl Resolve for HostsResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        // 端口由 reqwest 按 url 设置
+        let ips = self.lookup(&host);
+        let fallback = self.system_fallback;
+        Box::pin(async move {
+            if !ips.is_empty() {
+                let addrs: Addrs = Box::new(ips.into_iter().map(|ip| SocketAddr::new(ip, 0)));
+                return Ok(addrs);
+            }
+            if !fallback {
+                return Err(format!("域名 {} 未配置解析", host).into());
+            }
+            let addrs = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .collect::<Vec<_>>();
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// 用于 ClientKey，按指针区分不同的 resolver
+#[derive(Debug, Clone)]
+pub struct ResolverRef(pub Arc<HostsResolver>);
+
+impl PartialEq for ResolverRef {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for ResolverRef {}
+
+impl Hash for ResolverRef {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Arc::as_ptr(&self.0) as usize).hash(state)
+    }
+}
+
+// 支持 ip 或 ip:port
+fn parse_ip(s: &str) -> Option<IpAddr> {
+    let s = s.trim();
+    s.parse::<IpAddr>()
+        .ok()
+        .or_else(|| s.parse::<SocketAddr>().ok().map(|a| a.ip()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOSTS: &str = "
+# 注释
+1.1.1.1 api.a.com ws.a.com
+1.1.1.2 api.a.com   # 备用
+bad line
+";
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn round_robin() {
+        let r = HostsResolver::from_hosts_text(HOSTS, AddrOrder::RoundRobin);
+        r.add_addrs("API.a.com", ["1.1.1.3:443", "x"]);
+        assert_eq!(r.lookup("ws.a.com"), vec![ip("1.1.1.1")]);
+        let first = r.lookup("api.a.com");
+        assert_eq!(first.len(), 3);
+        assert_ne!(first[0], r.lookup("api.a.com")[0]);
+        assert!(r.lookup("b.com").is_empty());
+    }
+
+    #[test]
+    fn fastest_first() {
+        let r = HostsResolver::from_hosts_text(HOSTS, AddrOrder::FastestFirst);
+        r.add_addrs("api.a.com", ["1.1.1.3"]);
+        r.report_latency(ip("1.1.1.2"), Duration::from_millis(5));
+        r.report_latency(ip("1.1.1.3"), Duration::from_millis(1));
+        assert_eq!(
+            r.lookup("api.a.com"),
+            vec![ip("1.1.1.3"), ip("1.1.1.2"), ip("1.1.1.1")]
+        );
+    }
+}
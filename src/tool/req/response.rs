@@ -0,0 +1,191 @@
+//! 已读取完成的响应
+
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Arc;
+
+use reqwest::header::HeaderMap;
+use reqwest::{Response, StatusCode};
+use serde::de::DeserializeOwned;
+
+use super::{decode_json, ReqError};
+
+/// 判断状态码是否算请求成功，默认所有 2xx
+#[derive(Clone)]
+pub struct SuccessPolicy(Arc<dyn Fn(StatusCode) -> bool + Send + Sync>);
+
+impl SuccessPolicy {
+    /// 只有列出的状态码算成功
+    pub fn only(codes: impl IntoIterator<Item = u16>) -> Self {
+        let codes = codes.into_iter().collect::<HashSet<_>>();
+        Self::custom(move |s| codes.contains(&s.as_u16()))
+    }
+
+    /// 2xx 以及列出的状态码算成功，例如接口用 404 表示数据不存在
+    pub fn with_codes(codes: impl IntoIterator<Item = u16>) -> Self {
+        let codes = codes.into_iter().collect::<HashSet<_>>();
+        Self::custom(move |s| s.is_success() || codes.contains(&s.as_u16()))
+    }
+
+    pub fn custom(f: impl Fn(StatusCode) -> bool + Send + Sync + 'static) -> Self {
+        Self(Arc::new(f))
+    }
+
+    pub fn is_success(&self, status: StatusCode) -> bool {
+        (self.0)(status)
+    }
+}
+
+impl Default for SuccessPolicy {
+    fn default() -> Self {
+        Self::custom(|s| s.is_success())
+    }
+}
+
+impl fmt::Debug for SuccessPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SuccessPolicy")
+    }
+}
+
+/// 读取完 body 的响应
+#[derive(Debug, Clone)]
+pub struct ReqResponse {
+    pub url: String,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Vec<u8>,
+}
+
+impl ReqResponse {
+    /// 读取 reqwest 响应的全部内容
+    pub async fn read(resp: Response, url: &str) -> Result<Self, ReqError> {
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = resp
+            .bytes()
+            .await
+            .map_err(|err| ReqError::from_reqwest(err, url, &None))?;
+
+        Ok(Self {
+            url: url.to_string(),
+            status,
+            headers,
+            body: body.to_vec(),
+        })
+    }
+
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+
+    pub fn into_text(self) -> String {
+        String::from_utf8(self.body)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+    }
+
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, ReqError> {
+        decode_json(&self.url, &self.text())
+    }
+
+    /// 状态码是否为 2xx
+    pub fn is_success(&self) -> bool {
+        self.status.is_success()
+    }
+
+    /// 转换为状态码错误
+    pub fn into_status_error(self) -> ReqError {
+        let body = String::from_utf8_lossy(&self.body).into_owned();
+        ReqError::Status {
+            url: self.url,
+            code: self.status,
+            body,
+            headers: Box::new(self.headers),
+        }
+    }
+}
+
+impl ReqError {
+    /// 状态码错误时取回完整响应，便于解析交易所返回的错误信息
+    ///
+    /// ```ignore
+    /// if let Some(resp) = err.response() {
+    ///     let api_err: ApiError = resp.json()?;
+    /// }
+    /// ```
+    pub fn response(&self) -> Option<ReqResponse> {
+        match self {
+            ReqError::Status {
+                url,
+                code,
+                body,
+                headers,
+            } => Some(ReqResponse {
+                url: url.clone(),
+                status: *code,
+                headers: (**headers).clone(),
+                body: body.clone().into_bytes(),
+            }),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::*;
+    use crate::tool::req::{mock_response, Middleware, ReqContext, RequestSpec};
+
+    // 按 url 路径返回对应状态码
+    struct Status;
+
+    impl Middleware for Status {
+        fn before_send(&self, ctx: &mut ReqContext) -> Result<Option<Response>, ReqError> {
+            let code = ctx.url.rsplit('/').next().unwrap().parse::<u16>().unwrap();
+            let status = StatusCode::from_u16(code).unwrap();
+            Ok(Some(mock_response(
+                status,
+                r#"{"code":-1121,"msg":"Invalid symbol."}"#,
+            )))
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct ApiError {
+        code: i64,
+    }
+
+    fn spec(code: u16) -> RequestSpec {
+        RequestSpec::get(format!("http://mock.invalid/{}", code)).middleware(Status)
+    }
+
+    #[tokio::test]
+    async fn success_policy() {
+        assert!(spec(201).send().await.is_ok());
+        assert!(spec(204).send().await.is_ok());
+        assert!(spec(404).send().await.is_err());
+        assert!(spec(404)
+            .success(SuccessPolicy::with_codes([404]))
+            .send()
+            .await
+            .is_ok());
+        assert!(spec(200)
+            .success(SuccessPolicy::only([201]))
+            .send()
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn error_payload() {
+        let err = spec(400).send().await.unwrap_err();
+        let resp = err.response().unwrap();
+        assert_eq!(resp.status, StatusCode::BAD_REQUEST);
+        assert_eq!(resp.json::<ApiError>().unwrap().code, -1121);
+
+        let resp = spec(400).send_raw().await.unwrap();
+        assert!(!resp.is_success());
+    }
+}
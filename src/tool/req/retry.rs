@@ -0,0 +1,272 @@
+//! 请求重试策略：指数退避 + 抖动
+
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::time::Duration;
+
+use chrono::DateTime;
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use tracing::debug;
+
+use super::{get_with_timeout, post, ReqError, ReqErrorKind};
+
+/// 重试策略
+///
+/// 第 n 次重试前等待 `base_backoff * 2^(n-1)`，不超过 `max_backoff`，
+/// 再按 `jitter` 比例随机缩短，避免多个调用方同时重试
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// 最大尝试次数（包含第一次请求）
+    pub max_attempts: u32,
+    pub base_backoff: Duration,
+    pub max_backoff: Duration,
+    /// 抖动比例 0~1，实际等待时间在 [d * (1 - jitter), d] 之间；超出范围时按边界值处理，NaN 视为 0
+    pub jitter: f64,
+    /// 可重试的状态码
+    pub retry_status: HashSet<u16>,
+    /// 可重试的错误类型（状态码错误由 retry_status 决定）
+    pub retry_kinds: HashSet<ReqErrorKind>,
+    /// 所有错误都重试，忽略 retry_status 和 retry_kinds
+    pub retry_all: bool,
+    /// 响应带 Retry-After 时，按服务端要求的时间等待
+    pub respect_retry_after: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            base_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(10),
+            jitter: 0.5,
+            retry_status: [408, 429, 500, 502, 503, 504].into_iter().collect(),
+            retry_kinds: [
+                ReqErrorKind::Connect,
+                ReqErrorKind::Timeout,
+                ReqErrorKind::Proxy,
+            ]
+            .into_iter()
+            .collect(),
+            retry_all: false,
+            respect_retry_after: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    /// 不重试
+    pub fn none() -> Self {
+        Self::new(1)
+    }
+
+    pub fn backoff(mut self, base: Duration, max: Duration) -> Self {
+        self.base_backoff = base;
+        self.max_backoff = max;
+        self
+    }
+
+    pub fn jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn retry_status(mut self, code: u16) -> Self {
+        self.retry_status.insert(code);
+        self
+    }
+
+    pub fn retry_kind(mut self, kind: ReqErrorKind) -> Self {
+        self.retry_kinds.insert(kind);
+        self
+    }
+
+    pub fn retry_all(mut self, all: bool) -> Self {
+        self.retry_all = all;
+        self
+    }
+
+    pub fn respect_retry_after(mut self, respect: bool) -> Self {
+        self.respect_retry_after = respect;
+        self
+    }
+
+    /// 该错误是否需要重试
+    pub fn should_retry(&self, err: &ReqError) -> bool {
+        if self.retry_all {
+            return true;
+        }
+        match err.status() {
+            Some(code) => self.retry_status.contains(&code.as_u16()),
+            None => self.retry_kinds.contains(&err.kind()),
+        }
+    }
+
+    /// 第 attempt 次失败后的退避时间（attempt 从 1 开始）
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let d = self
+            .base_backoff
+            .saturating_mul(1u32 << exp)
+            .min(self.max_backoff);
+
+        // 字段可以直接修改，这里再限制一次范围
+        let jitter = match self.jitter.is_nan() {
+            true => 0.0,
+            false => self.jitter.clamp(0.0, 1.0),
+        };
+        if jitter == 0.0 || d.is_zero() {
+            return d;
+        }
+        let factor = 1.0 - rand::thread_rng().gen_range(0.0..=jitter);
+        d.mul_f64(factor)
+    }
+
+    /// 实际等待时间：优先使用 Retry-After，不超过 max_backoff
+    pub fn delay_for(&self, attempt: u32, err: &ReqError) -> Duration {
+        if self.respect_retry_after {
+            if let Some(d) = retry_after(err) {
+                return d.min(self.max_backoff);
+            }
+        }
+        self.backoff_for(attempt)
+    }
+
+    /// 按策略执行 f，直到成功、遇到不可重试的错误或次数用完
+    pub async fn run<T, F, Fut>(&self, mut f: F) -> Result<T, ReqError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ReqError>>,
+    {
+        let attempts = self.max_attempts.max(1);
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let err = match f().await {
+                Ok(v) => return Ok(v),
+                Err(err) => err,
+            };
+            if attempt >= attempts || !self.should_retry(&err) {
+                return Err(err);
+            }
+
+            let wait = self.delay_for(attempt, &err);
+            debug!("第 {} 次请求失败，{:?} 后重试：{}", attempt, wait, err);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// 解析 Retry-After 头，支持秒数和 HTTP 日期两种格式
+fn retry_after(err: &ReqError) -> Option<Duration> {
+    let headers = match err {
+        ReqError::Status { headers, .. } => headers,
+        _ => return None,
+    };
+    let v = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(secs) = v.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let at = DateTime::parse_from_rfc2822(v).ok()?;
+    let ms = at.timestamp_millis() - crate::tool::libtime::get_now_millis();
+    Some(Duration::from_millis(ms.max(0) as u64))
+}
+
+/// 按重试策略执行 GET
+pub async fn get_with_retry(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    timeout: Duration,
+    policy: &RetryPolicy,
+) -> Result<String, ReqError> {
+    policy
+        .run(|| get_with_timeout(url, req_head, proxy, timeout))
+        .await
+}
+
+/// 按重试策略执行 POST
+pub async fn post_with_retry(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    body: String,
+    timeout: Duration,
+    policy: &RetryPolicy,
+) -> Result<String, ReqError> {
+    policy
+        .run(|| post(url, req_head, proxy, body.clone(), timeout))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderMap;
+    use reqwest::StatusCode;
+
+    fn status_err(code: u16, retry_after: Option<&str>) -> ReqError {
+        let mut headers = HeaderMap::new();
+        if let Some(v) = retry_after {
+            headers.insert(RETRY_AFTER, v.parse().unwrap());
+        }
+        ReqError::Status {
+            url: "http://127.0.0.1/".to_string(),
+            code: StatusCode::from_u16(code).unwrap(),
+            body: String::new(),
+            headers: Box::new(headers),
+        }
+    }
+
+    #[test]
+    fn backoff_grows_and_caps() {
+        let p = RetryPolicy::new(10)
+            .backoff(Duration::from_millis(100), Duration::from_millis(500))
+            .jitter(0.0);
+        assert_eq!(p.backoff_for(1), Duration::from_millis(100));
+        assert_eq!(p.backoff_for(2), Duration::from_millis(200));
+        assert_eq!(p.backoff_for(3), Duration::from_millis(400));
+        assert_eq!(p.backoff_for(4), Duration::from_millis(500));
+        assert_eq!(p.backoff_for(40), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn retryable_errors() {
+        let p = RetryPolicy::default();
+        assert!(p.should_retry(&status_err(503, None)));
+        assert!(!p.should_retry(&status_err(404, None)));
+        assert!(p.should_retry(&ReqError::Timeout { url: "".into() }));
+        assert!(!p.should_retry(&ReqError::Decode {
+            url: "".into(),
+            msg: "".into()
+        }));
+        assert_eq!(
+            p.delay_for(1, &status_err(429, Some("3"))),
+            Duration::from_secs(3)
+        );
+        // Retry-After 超过 max_backoff 时按 max_backoff 等待
+        assert_eq!(
+            p.delay_for(1, &status_err(429, Some("86400"))),
+            p.max_backoff
+        );
+    }
+
+    #[test]
+    fn invalid_jitter() {
+        let mut p = RetryPolicy::new(3).backoff(Duration::from_millis(100), Duration::from_secs(1));
+        p.jitter = f64::NAN;
+        assert_eq!(p.backoff_for(1), Duration::from_millis(100));
+        p.jitter = 5.0;
+        assert!(p.backoff_for(1) <= Duration::from_millis(100));
+        p.jitter = -1.0;
+        assert_eq!(p.backoff_for(1), Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,362 @@
+//! 交易所 REST 接口签名（HMAC-SHA256）
+
+use std::fmt;
+
+use chrono::Utc;
+use data_encoding::{BASE64, HEXLOWER};
+use hmac::{Hmac, Mac};
+use reqwest::{Method, Url};
+use sha2::Sha256;
+
+use super::ReqError;
+use crate::tool::libtime;
+
+/// 待签名的请求，签名器可修改查询参数和请求头
+pub struct SignRequest<'a> {
+    pub method: &'a Method,
+    /// 不含追加查询参数的原始 url
+    pub url: &'a str,
+    pub query: &'a mut Vec<(String, String)>,
+    pub headers: &'a mut Vec<(String, String)>,
+    pub body: Option<&'a [u8]>,
+}
+
+impl SignRequest<'_> {
+    /// 最终发送的查询字符串（原始 url 中的参数 + 追加的参数）
+    pub fn query_string(&self) -> String {
+        let appended = serde_urlencoded::to_string(&*self.query).unwrap_or_default();
+        let origin = self.url.split_once('?').map(|(_, q)| q).unwrap_or_default();
+        match (origin.is_empty(), appended.is_empty()) {
+            (true, _) => appended,
+            (false, true) => origin.to_string(),
+            (false, false) => format!("{}&{}", origin, appended),
+        }
+    }
+
+    /// url 路径
+    pub fn path(&self) -> Result<String, ReqError> {
+        let url = self.url.parse::<Url>().map_err(|err| ReqError::Build {
+            url: self.url.to_string(),
+            msg: err.to_string(),
+        })?;
+        Ok(url.path().to_string())
+    }
+
+    pub fn body_str(&self) -> &str {
+        self.body
+            .and_then(|b| std::str::from_utf8(b).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// 请求签名器
+pub trait Signer: Send + Sync + fmt::Debug {
+    fn sign(&self, req: &mut SignRequest<'_>) -> Result<(), ReqError>;
+}
+
+pub fn hmac_sha256(secret: &str, payload: &str) -> Vec<u8> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(payload.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+pub fn hmac_sha256_hex(secret: &str, payload: &str) -> String {
+    HEXLOWER.encode(&hmac_sha256(secret, payload))
+}
+
+pub fn hmac_sha256_base64(secret: &str, payload: &str) -> String {
+    BASE64.encode(&hmac_sha256(secret, payload))
+}
+
+/// 查询字符串签名（Binance 风格）
+///
+/// 追加 `recvWindow`、`timestamp` 参数，对 `查询字符串 + body` 做 HMAC，
+/// 结果以 hex 追加为 `signature` 参数，api key 放在请求头
+#[derive(Clone)]
+pub struct QuerySigner {
+    pub api_key: String,
+    secret: String,
+    pub key_header: String,
+    /// 单位 ms；None 时不追加
+    pub recv_window: Option<u64>,
+    /// 签名前按 key 排序查询参数（默认开启，相同 key 保持原有顺序）；
+    /// 关闭时按添加顺序签名
+    pub sort: bool,
+    pub timestamp_key: String,
+    pub recv_window_key: String,
+    pub signature_key: String,
+}
+
+impl QuerySigner {
+    pub fn new(api_key: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            secret: secret.into(),
+            key_header: "X-MBX-APIKEY".to_string(),
+            recv_window: Some(5000),
+            sort: true,
+            timestamp_key: "timestamp".to_string(),
+            recv_window_key: "recvWindow".to_string(),
+            signature_key: "signature".to_string(),
+        }
+    }
+
+    pub fn key_header(mut self, header: impl Into<String>) -> Self {
+        self.key_header = header.into();
+        self
+    }
+
+    pub fn recv_window(mut self, ms: Option<u64>) -> Self {
+        self.recv_window = ms;
+        self
+    }
+
+    pub fn sort(mut self, sort: bool) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// 使用指定时间戳签名
+    pub fn sign_at(&self, req: &mut SignRequest<'_>, ts: i64) -> Result<(), ReqError> {
+        if let Some(w) = self.recv_window {
+            req.query
+                .push((self.recv_window_key.clone(), w.to_string()));
+        }
+        req.query.push((self.timestamp_key.clone(), ts.to_string()));
+        if self.sort {
+            req.query.sort_by(|a, b| a.0.cmp(&b.0));
+        }
+
+        let payload = format!("{}{}", req.query_string(), req.body_str());
+        let sig = hmac_sha256_hex(&self.secret, &payload);
+        req.query.push((self.signature_key.clone(), sig));
+        req.headers
+            .push((self.key_header.clone(), self.api_key.clone()));
+        Ok(())
+    }
+}
+
+impl Signer for QuerySigner {
+    fn sign(&self, req: &mut SignRequest<'_>) -> Result<(), ReqError> {
+        self.sign_at(req, libtime::get_now_millis())
+    }
+}
+
+impl fmt::Debug for QuerySigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("QuerySigner")
+            .field("api_key", &self.api_key)
+            .field("recv_window", &self.recv_window)
+            .finish()
+    }
+}
+
+/// 时间戳格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// 毫秒数
+    Millis,
+    /// ISO 8601，精确到毫秒，例如 2020-12-08T09:08:57.715Z
+    Iso8601,
+}
+
+/// 预签名字符串签名（OKX / Bitget 风格）
+///
+/// 对 `timestamp + METHOD + path?query + body` 做 HMAC，结果以 base64 放在请求头
+#[derive(Clone)]
+pub struct PrehashSigner {
+    pub api_key: String,
+    secret: String,
+    passphrase: Option<String>,
+    pub ts_format: TimestampFormat,
+    pub key_header: String,
+    pub sign_header: String,
+    pub timestamp_header: String,
+    pub passphrase_header: String,
+}
+
+impl PrehashSigner {
+    /// 默认使用 OKX 的请求头名称
+    pub fn new(api_key: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            api_key: api_key.into(),
+            secret: secret.into(),
+            passphrase: None,
+            ts_format: TimestampFormat::Iso8601,
+            key_header: "OK-ACCESS-KEY".to_string(),
+            sign_header: "OK-ACCESS-SIGN".to_string(),
+            timestamp_header: "OK-ACCESS-TIMESTAMP".to_string(),
+            passphrase_header: "OK-ACCESS-PASSPHRASE".to_string(),
+        }
+    }
+
+    pub fn passphrase(mut self, passphrase: impl Into<String>) -> Self {
+        self.passphrase = Some(passphrase.into());
+        self
+    }
+
+    pub fn ts_format(mut self, format: TimestampFormat) -> Self {
+        self.ts_format = format;
+        self
+    }
+
+    /// 设置请求头名称：api key、签名、时间戳、passphrase
+    pub fn headers(
+        mut self,
+        key: impl Into<String>,
+        sign: impl Into<String>,
+        timestamp: impl Into<String>,
+        passphrase: impl Into<String>,
+    ) -> Self {
+        self.key_header = key.into();
+        self.sign_header = sign.into();
+        self.timestamp_header = timestamp.into();
+        self.passphrase_header = passphrase.into();
+        self
+    }
+
+    /// 待签名字符串
+    pub fn prehash(req: &SignRequest<'_>, ts: &str) -> Result<String, ReqError> {
+        let query = req.query_string();
+        let mut path = req.path()?;
+        if !query.is_empty() {
+            path = format!("{}?{}", path, query);
+        }
+        Ok(format!(
+            "{}{}{}{}",
+            ts,
+            req.method.as_str(),
+            path,
+            req.body_str()
+        ))
+    }
+
+    /// 使用指定时间戳签名
+    pub fn sign_at(&self, req: &mut SignRequest<'_>, ts: &str) -> Result<(), ReqError> {
+        let sig = hmac_sha256_base64(&self.secret, &Self::prehash(req, ts)?);
+
+        req.headers
+            .push((self.key_header.clone(), self.api_key.clone()));
+        req.headers.push((self.sign_header.clone(), sig));
+        req.headers
+            .push((self.timestamp_header.clone(), ts.to_string()));
+        if let Some(p) = &self.passphrase {
+            req.headers
+                .push((self.passphrase_header.clone(), p.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl Signer for PrehashSigner {
+    fn sign(&self, req: &mut SignRequest<'_>) -> Result<(), ReqError> {
+        let ts = match self.ts_format {
+            TimestampFormat::Millis => libtime::get_now_millis().to_string(),
+            TimestampFormat::Iso8601 => Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string(),
+        };
+        self.sign_at(req, &ts)
+    }
+}
+
+impl fmt::Debug for PrehashSigner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PrehashSigner")
+            .field("api_key", &self.api_key)
+            .field("ts_format", &self.ts_format)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn query_signer() {
+        // Binance 文档中的示例，按文档中的参数顺序签名
+        let s = QuerySigner::new(
+            "vmPUZE6mv9SD5VNHk4HlWFsOr6aKE2zvsw0MuIgwCIPy6utIco14y7Ju91duEh8A",
+            "NhqPtmdSJYdKjVHjA7PZj4Mge3R5YNiP1e3UZjInClVN65XAbvqqM6A7H5fATj0j",
+        )
+        .sort(false);
+        let mut query = [
+            ("symbol", "LTCBTC"),
+            ("side", "BUY"),
+            ("type", "LIMIT"),
+            ("timeInForce", "GTC"),
+            ("quantity", "1"),
+            ("price", "0.1"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect::<Vec<_>>();
+        let mut headers = Vec::new();
+        let mut req = SignRequest {
+            method: &Method::POST,
+            url: "https://api.binance.com/api/v3/order",
+            query: &mut query,
+            headers: &mut headers,
+            body: None,
+        };
+        s.sign_at(&mut req, 1499827319559).unwrap();
+
+        assert_eq!(
+            query.last().unwrap().1,
+            "c8db56825ae71d6d79447849e617115f4a920fa2acdcab2b053c4b2838bd6b71"
+        );
+        assert_eq!(headers[0].0, "X-MBX-APIKEY");
+    }
+
+    #[test]
+    fn query_signer_sorts_by_default() {
+        let sign = |pairs: &[(&str, &str)]| {
+            let mut query = pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<Vec<_>>();
+            let mut headers = Vec::new();
+            let mut req = SignRequest {
+                method: &Method::GET,
+                url: "https://api.binance.com/api/v3/openOrders",
+                query: &mut query,
+                headers: &mut headers,
+                body: None,
+            };
+            QuerySigner::new("k", "s").sign_at(&mut req, 1).unwrap();
+            query
+        };
+        let a = sign(&[("symbol", "BTCUSDT"), ("limit", "5")]);
+        let b = sign(&[("limit", "5"), ("symbol", "BTCUSDT")]);
+        assert_eq!(a, b);
+        let keys = a.iter().map(|(k, _)| k.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            keys,
+            ["limit", "recvWindow", "symbol", "timestamp", "signature"]
+        );
+    }
+
+    #[test]
+    fn prehash_signer() {
+        let s = PrehashSigner::new("key", "secret").passphrase("pass");
+        let mut query = vec![("ccy".to_string(), "BTC".to_string())];
+        let mut headers = Vec::new();
+        let mut req = SignRequest {
+            method: &Method::GET,
+            url: "https://www.okx.com/api/v5/account/balance",
+            query: &mut query,
+            headers: &mut headers,
+            body: None,
+        };
+        s.sign_at(&mut req, "2020-12-08T09:08:57.715Z").unwrap();
+
+        assert_eq!(
+            headers[1],
+            (
+                "OK-ACCESS-SIGN".to_string(),
+                "wpDvCwYCprcMQsQkxWJiWy+YADoQE4ep+OEKKLimMoY=".to_string()
+            )
+        );
+        assert_eq!(headers.len(), 4);
+    }
+}
@@ -0,0 +1,613 @@
+//! 构建式请求，替代多参数的 exec_req
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Method, Response, Url};
+use serde::Serialize;
+use tracing::field::Empty;
+use tracing::{debug, info_span, Instrument, Span};
+
+use crate::tool::probe::Prober;
+use crate::tool::tls::TlsOptions;
+
+use super::{
+    current_transport, global_middlewares, metrics, rate_limiter, status_label, ClientKey,
+    ClientOptions, HostsResolver, Middleware, MiddlewareStack, ProxyPool, ReqContext, ReqError,
+    ReqResponse, ResponseCache, SignRequest, Signer, SuccessPolicy, Transport, TransportRequest,
+};
+
+/// 默认超时时间
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 请求描述
+///
+/// ```ignore
+/// let resp = RequestSpec::get("https://api.example.com/ticker")
+///     .query("symbol", "BTCUSDT")
+///     .timeout(Duration::from_secs(3))
+///     .send()
+///     .await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestSpec {
+    pub(crate) method: Method,
+    pub(crate) url: String,
+    pub(crate) headers: Vec<(String, String)>,
+    pub(crate) query: Vec<(String, String)>,
+    pub(crate) body: Option<Vec<u8>>,
+    pub(crate) proxy: Option<String>,
+    pub(crate) dns: Option<String>,
+    pub(crate) resolver: Option<Arc<HostsResolver>>,
+    pub(crate) timeout: Duration,
+    pub(crate) force_new_client: bool,
+    pub(crate) client_options: ClientOptions,
+    pub(crate) tls: Option<TlsOptions>,
+    pub(crate) signer: Option<Arc<dyn Signer>>,
+    pub(crate) weight: Option<u32>,
+    pub(crate) proxy_pool: Option<Arc<ProxyPool>>,
+    pub(crate) middlewares: MiddlewareStack,
+    pub(crate) success: SuccessPolicy,
+    pub(crate) cache: Option<Arc<ResponseCache>>,
+    pub(crate) transport: Option<Arc<dyn Transport>>,
+    // 构建阶段出现的错误，在发送时返回
+    pub(crate) error: Option<ReqError>,
+}
+
+impl RequestSpec {
+    pub fn new(method: Method, url: impl Into<String>) -> Self {
+        Self {
+            method,
+            url: url.into(),
+            headers: Vec::new(),
+            query: Vec::new(),
+            body: None,
+            proxy: None,
+            dns: None,
+            resolver: None,
+            timeout: DEFAULT_TIMEOUT,
+            force_new_client: false,
+            client_options: ClientOptions::default(),
+            tls: None,
+            signer: None,
+            weight: None,
+            proxy_pool: None,
+            middlewares: MiddlewareStack::default(),
+            success: SuccessPolicy::default(),
+            cache: None,
+            transport: None,
+            error: None,
+        }
+    }
+
+    pub fn get(url: impl Into<String>) -> Self {
+        Self::new(Method::GET, url)
+    }
+
+    pub fn post(url: impl Into<String>) -> Self {
+        Self::new(Method::POST, url)
+    }
+
+    pub fn put(url: impl Into<String>) -> Self {
+        Self::new(Method::PUT, url)
+    }
+
+    pub fn delete(url: impl Into<String>) -> Self {
+        Self::new(Method::DELETE, url)
+    }
+
+    pub fn patch(url: impl Into<String>) -> Self {
+        Self::new(Method::PATCH, url)
+    }
+
+    pub fn header(mut self, k: impl Into<String>, v: impl Into<String>) -> Self {
+        self.headers.push((k.into(), v.into()));
+        self
+    }
+
+    pub fn headers<K, V>(mut self, headers: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<String>,
+        V: Into<String>,
+    {
+        for (k, v) in headers {
+            self.headers.push((k.into(), v.into()));
+        }
+        self
+    }
+
+    /// 追加 url 查询参数
+    pub fn query(mut self, k: impl Into<String>, v: impl ToString) -> Self {
+        self.query.push((k.into(), v.to_string()));
+        self
+    }
+
+    /// 原始文本 body
+    pub fn body(mut self, body: impl Into<String>) -> Self {
+        self.body = Some(body.into().into_bytes());
+        self
+    }
+
+    /// 二进制 body
+    pub fn bytes(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// json body，未设置 Content-Type 时设置为 application/json
+    pub fn json<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        match serde_json::to_vec(body) {
+            Ok(b) => {
+                self.body = Some(b);
+                self.default_content_type("application/json")
+            }
+            Err(err) => self.fail(err.to_string()),
+        }
+    }
+
+    /// 表单 body，未设置 Content-Type 时设置为 application/x-www-form-urlencoded
+    pub fn form<T: Serialize + ?Sized>(mut self, body: &T) -> Self {
+        match serde_urlencoded::to_string(body) {
+            Ok(b) => {
+                self.body = Some(b.into_bytes());
+                self.default_content_type("application/x-www-form-urlencoded")
+            }
+            Err(err) => self.fail(err.to_string()),
+        }
+    }
+
+    // 已有 Content-Type（例如 req_head 中带的）时保留，避免发送重复的请求头
+    fn default_content_type(self, ct: &str) -> Self {
+        let exists = self
+            .headers
+            .iter()
+            .any(|(k, _)| k.eq_ignore_ascii_case(CONTENT_TYPE.as_str()));
+        match exists {
+            true => self,
+            false => self.header(CONTENT_TYPE.as_str(), ct),
+        }
+    }
+
+    pub fn proxy(mut self, proxy: &Option<String>) -> Self {
+        self.proxy = proxy.clone();
+        self
+    }
+
+    /// 未指定 proxy 时从代理池中选取，并按请求结果更新代理状态
+    pub fn proxy_pool(mut self, pool: Arc<ProxyPool>) -> Self {
+        self.proxy_pool = Some(pool);
+        self
+    }
+
+    /// 固定 url 域名解析到的地址，格式 ip:port
+    pub fn dns(mut self, addr: impl Into<String>) -> Self {
+        self.dns = Some(addr.into());
+        self
+    }
+
+    /// 固定解析到 prober 测得最快的地址，没有可用地址时不修改
+    pub fn fastest(self, prober: &Prober) -> Self {
+        let host = self
+            .url
+            .parse::<Url>()
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string));
+        match host.and_then(|h| prober.best_addr(&h)) {
+            Some(addr) => self.dns(addr.to_string()),
+            None => self,
+        }
+    }
+
+    /// 使用自定义域名解析，可配置多个域名、多个 ip
+    pub fn resolver(mut self, resolver: Arc<HostsResolver>) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// 是否强制使用新的cli
+    pub fn force_new_client(mut self, force: bool) -> Self {
+        self.force_new_client = force;
+        self
+    }
+
+    /// Client 连接参数，不同参数使用不同的 Client
+    pub fn client_options(mut self, options: ClientOptions) -> Self {
+        self.client_options = options;
+        self
+    }
+
+    /// TLS 配置，默认使用全局配置
+    pub fn tls(mut self, tls: TlsOptions) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// 发送前使用 signer 签名，每次发送都会重新签名
+    pub fn signer(mut self, signer: Arc<dyn Signer>) -> Self {
+        self.signer = Some(signer);
+        self
+    }
+
+    /// 限频时该请求占用的权重，默认按限频配置中的接口权重
+    pub fn weight(mut self, weight: u32) -> Self {
+        self.weight = Some(weight);
+        self
+    }
+
+    /// 追加中间件，在全局中间件之后调用
+    pub fn middleware(mut self, m: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(m));
+        self
+    }
+
+    /// 追加一组中间件，例如某个服务所有请求共用的中间件
+    pub fn middlewares(mut self, stack: &MiddlewareStack) -> Self {
+        self.middlewares.extend(stack);
+        self
+    }
+
+    /// 指定发送层，优先于 with_transport 和全局配置，一般用于测试
+    pub fn transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    /// send 时判断状态码是否成功，默认所有 2xx
+    pub fn success(mut self, policy: SuccessPolicy) -> Self {
+        self.success = policy;
+        self
+    }
+
+    /// send 时使用缓存，只对 GET 生效
+    pub fn cache(mut self, cache: Arc<ResponseCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    fn fail(mut self, msg: String) -> Self {
+        if self.error.is_none() {
+            self.error = Some(ReqError::Build {
+                url: self.url.clone(),
+                msg,
+            });
+        }
+        self
+    }
+
+    /// 拼接查询参数后的完整 url
+    pub fn full_url(&self) -> Result<String, ReqError> {
+        if self.query.is_empty() {
+            return Ok(self.url.clone());
+        }
+        let mut url = self.url.parse::<Url>().map_err(|err| ReqError::Build {
+            url: self.url.clone(),
+            msg: err.to_string(),
+        })?;
+        url.query_pairs_mut().extend_pairs(self.query.iter());
+        Ok(url.to_string())
+    }
+
+    pub fn client_key(&self) -> Result<ClientKey, ReqError> {
+        self.key_for(&self.proxy, &self.url)
+    }
+
+    fn key_for(&self, proxy: &Option<String>, url: &str) -> Result<ClientKey, ReqError> {
+        let mut key = ClientKey::new(proxy, url, self.dns.as_deref(), self.timeout)?
+            .with_options(self.client_options.clone());
+        if let Some(tls) = &self.tls {
+            key = key.with_tls(tls.clone());
+        }
+        if let Some(r) = &self.resolver {
+            key = key.with_resolver(r.clone());
+        }
+        Ok(key)
+    }
+
+    /// 发送请求，返回原始响应，不检查状态码
+    pub async fn execute(self) -> Result<Response, ReqError> {
+        let (ctx, _, trace, rs) = self.run().await;
+        // 不读取 body，流量按 Content-Length 统计
+        let bytes = rs.as_ref().ok().and_then(Response::content_length);
+        trace.finish(&ctx, rs.as_ref().map(|r| r.status().as_u16()), bytes);
+        rs
+    }
+
+    /// 发送请求并读取响应，状态码不满足 [`SuccessPolicy`] 时返回 [`ReqError::Status`]
+    pub async fn send(mut self) -> Result<ReqResponse, ReqError> {
+        match self.cache.take() {
+            Some(cache) => cache.fetch(self).await,
+            None => self.send_uncached().await,
+        }
+    }
+
+    pub(crate) async fn send_uncached(self) -> Result<ReqResponse, ReqError> {
+        let success = self.success.clone();
+        let (ctx, stack, span, resp) = self.read().await;
+        let _enter = span.enter();
+        check_resp(resp, &ctx, &stack, &success)
+    }
+
+    /// 发送请求并读取响应，不检查状态码，调用方根据 status 自行处理错误内容
+    pub async fn send_raw(self) -> Result<ReqResponse, ReqError> {
+        let (ctx, stack, span, resp) = self.read().await;
+        if let Err(err) = &resp {
+            span.in_scope(|| stack.on_error(&ctx, err));
+        }
+        resp
+    }
+
+    // 发送请求并读取全部 body
+    async fn read(
+        self,
+    ) -> (
+        ReqContext,
+        MiddlewareStack,
+        Span,
+        Result<ReqResponse, ReqError>,
+    ) {
+        let (ctx, stack, trace, rs) = self.run().await;
+        let resp = match rs {
+            Ok(resp) => {
+                ReqResponse::read(resp, &ctx.url)
+                    .instrument(trace.span.clone())
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        let bytes = resp.as_ref().ok().map(|r| r.body.len() as u64);
+        trace.finish(&ctx, resp.as_ref().map(|r| r.status.as_u16()), bytes);
+        (ctx, stack, trace.span, resp)
+    }
+
+    // 执行请求，同时返回最终的请求内容、使用的中间件和本次请求的 span
+    async fn run(
+        self,
+    ) -> (
+        ReqContext,
+        MiddlewareStack,
+        CallTrace,
+        Result<Response, ReqError>,
+    ) {
+        let mut stack = global_middlewares();
+        stack.extend(&self.middlewares);
+        let mut ctx = ReqContext {
+            method: self.method.clone(),
+            url: self.url.clone(),
+            headers: self.headers.clone(),
+            body: None,
+            proxy: self.proxy.clone(),
+        };
+        let trace = CallTrace::start(&self.method, &self.url);
+        let rs = self
+            .dispatch(&mut ctx, &stack)
+            .instrument(trace.span.clone())
+            .await;
+        (ctx, stack, trace, rs)
+    }
+
+    async fn dispatch(
+        mut self,
+        ctx: &mut ReqContext,
+        stack: &MiddlewareStack,
+    ) -> Result<Response, ReqError> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        if let Some(signer) = self.signer.take() {
+            signer.sign(&mut SignRequest {
+                method: &self.method,
+                url: &self.url,
+                query: &mut self.query,
+                headers: &mut self.headers,
+                body: self.body.as_deref(),
+            })?;
+        }
+        let url = self.full_url()?;
+
+        let pool = match (self.proxy_pool.take(), &self.proxy) {
+            (Some(pool), None) => {
+                let p = pool.next().ok_or_else(|| ReqError::Proxy {
+                    proxy: "proxy pool".to_string(),
+                    msg: "没有可用的代理".to_string(),
+                })?;
+                self.proxy = Some(p.clone());
+                Some((pool, p))
+            }
+            _ => None,
+        };
+
+        *ctx = ReqContext {
+            method: self.method.clone(),
+            url,
+            headers: std::mem::take(&mut self.headers),
+            body: self.body.take(),
+            proxy: self.proxy.clone(),
+        };
+        let mut rs = match stack.before_send(ctx) {
+            Ok(Some(resp)) => Ok(resp),
+            Ok(None) => self.transmit(ctx, stack).await,
+            Err(err) => Err(err),
+        };
+        if let Some((pool, p)) = &pool {
+            pool.report(p, rs.as_ref().err().map(ReqError::kind));
+        }
+        stack.after_receive(ctx, &mut rs);
+        rs
+    }
+
+    // 限频后实际发送请求
+    async fn transmit(
+        &self,
+        ctx: &ReqContext,
+        stack: &MiddlewareStack,
+    ) -> Result<Response, ReqError> {
+        // 限频：按域名申请额度
+        let limiter = rate_limiter().and_then(|l| {
+            let u = ctx.url.parse::<Url>().ok()?;
+            Some((l, u.host_str()?.to_string(), u.path().to_string()))
+        });
+        if let Some((l, host, path)) = &limiter {
+            let weight = self.weight.unwrap_or_else(|| l.weight_of(host, path));
+            l.acquire(host, weight).await;
+        }
+
+        let key = self.key_for(&ctx.proxy, &ctx.url)?;
+        let transport = self.transport.clone().unwrap_or_else(current_transport);
+        let resp = transport
+            .send(TransportRequest {
+                ctx,
+                key: &key,
+                force_new_client: self.force_new_client,
+                stack,
+            })
+            .await?;
+        if let Some((l, host, _)) = &limiter {
+            l.observe(host, resp.headers());
+        }
+        Ok(resp)
+    }
+}
+
+// 单次请求的 span 和计时，结束时记录状态、耗时、流量并写入指标
+struct CallTrace {
+    span: Span,
+    host: String,
+    start: Instant,
+}
+
+impl CallTrace {
+    fn start(method: &Method, url: &str) -> Self {
+        let host = url
+            .parse::<Url>()
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_default();
+        let span = info_span!(
+            "http",
+            method = %method,
+            host = %host,
+            proxy = Empty,
+            status = Empty,
+            latency_ms = Empty,
+            bytes = Empty,
+        );
+        Self {
+            span,
+            host,
+            start: Instant::now(),
+        }
+    }
+
+    fn finish(&self, ctx: &ReqContext, rs: Result<u16, &ReqError>, bytes: Option<u64>) {
+        let latency = self.start.elapsed();
+        let status = status_label(rs);
+        let bytes = bytes.unwrap_or_default();
+
+        let span = &self.span;
+        span.record("proxy", ctx.proxy.as_deref().unwrap_or("-"));
+        span.record("status", status.as_str());
+        span.record("latency_ms", latency.as_millis() as u64);
+        span.record("bytes", bytes);
+        span.in_scope(|| debug!("请求完成"));
+
+        metrics().observe(&self.host, &status, latency, bytes);
+    }
+}
+
+// 检查状态码，失败时交给中间件处理（默认记录带请求头的错误信息）
+pub(crate) fn check_resp(
+    resp: Result<ReqResponse, ReqError>,
+    ctx: &ReqContext,
+    stack: &MiddlewareStack,
+    success: &SuccessPolicy,
+) -> Result<ReqResponse, ReqError> {
+    let err = match resp {
+        Ok(resp) if success.is_success(resp.status) => return Ok(resp),
+        Ok(resp) => resp.into_status_error(),
+        Err(err) => err,
+    };
+    stack.on_error(ctx, &err);
+    Err(err)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::tool::req::{spec_of, MockReply, MockTransport};
+
+    const URL: &str = "http://mock.invalid/api";
+
+    fn header<'a>(ctx: &'a ReqContext, k: &str) -> Vec<&'a str> {
+        ctx.headers
+            .iter()
+            .filter(|(n, _)| n.eq_ignore_ascii_case(k))
+            .map(|(_, v)| v.as_str())
+            .collect()
+    }
+
+    // 发送后返回 mock 收到的请求
+    async fn sent(spec: RequestSpec) -> ReqContext {
+        let mock = Arc::new(MockTransport::new().on(spec.method.clone(), URL, MockReply::ok("")));
+        spec.transport(mock.clone()).send().await.unwrap();
+        mock.requests().remove(0)
+    }
+
+    #[tokio::test]
+    async fn query_is_encoded() {
+        let ctx = sent(
+            RequestSpec::get(URL)
+                .query("symbol", "BTC USDT")
+                .query("limit", 5),
+        )
+        .await;
+        assert_eq!(ctx.url, format!("{}?symbol=BTC+USDT&limit=5", URL));
+        assert_eq!(ctx.body, None);
+
+        // url 中已有的查询参数保留
+        let ctx = sent(RequestSpec::get(format!("{}?a=1", URL)).query("b", 2)).await;
+        assert_eq!(ctx.url, format!("{}?a=1&b=2", URL));
+    }
+
+    #[tokio::test]
+    async fn json_and_form_body() {
+        let req_head = HashMap::from([("X-Api".to_string(), "k".to_string())]);
+        let spec = spec_of(
+            Method::POST,
+            URL,
+            Some(&req_head),
+            &None,
+            Duration::from_secs(1),
+        );
+        let ctx = sent(spec.json(&json!({"a": 1}))).await;
+        assert_eq!(ctx.method, Method::POST);
+        assert_eq!(ctx.body.as_deref(), Some(br#"{"a":1}"#.as_slice()));
+        assert_eq!(header(&ctx, "content-type"), ["application/json"]);
+        assert_eq!(header(&ctx, "x-api"), ["k"]);
+
+        let ctx = sent(RequestSpec::post(URL).form(&[("a", "1"), ("b", "x y")])).await;
+        assert_eq!(ctx.body.as_deref(), Some(b"a=1&b=x+y".as_slice()));
+        assert_eq!(
+            header(&ctx, "content-type"),
+            ["application/x-www-form-urlencoded"]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+//! 请求发送层：默认通过 reqwest 发送，测试时可以替换为 [`MockTransport`](super::MockTransport)
+//!
+//! 优先级：RequestSpec::transport > with_transport > set_global_transport > HttpTransport
+//!
+//! ```ignore
+//! let mock = Arc::new(MockTransport::new().get("http://list/black", "a,b"));
+//! let mut set = HashSet::new();
+//! with_transport(mock.clone(), merge("http://list/black", &mut set, 1)).await?;
+//! assert_eq!(mock.hits("http://list/black"), 1);
+//! ```
+
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use reqwest::Response;
+
+use super::{client_for, ClientKey, MiddlewareStack, ReqContext, ReqError};
+
+pub type TransportFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Response, ReqError>> + Send + 'a>>;
+
+/// 经过中间件、限频后实际发送的请求
+pub struct TransportRequest<'a> {
+    pub ctx: &'a ReqContext,
+    /// 按请求配置生成的 Client 参数
+    pub key: &'a ClientKey,
+    pub force_new_client: bool,
+    pub stack: &'a MiddlewareStack,
+}
+
+/// 发送请求并返回响应头，body 由调用方读取
+pub trait Transport: Send + Sync + fmt::Debug {
+    fn send<'a>(&'a self, req: TransportRequest<'a>) -> TransportFuture<'a>;
+}
+
+/// 默认实现：使用缓存的 reqwest Client 发送
+#[derive(Debug, Default)]
+pub struct HttpTransport;
+
+impl Transport for HttpTransport {
+    fn send<'a>(&'a self, req: TransportRequest<'a>) -> TransportFuture<'a> {
+        Box::pin(async move {
+            let ctx = req.ctx;
+            let cli = client_for(req.key, req.force_new_client, req.stack)?;
+
+            let mut req_build = cli.request(ctx.method.clone(), &ctx.url);
+            for (k, v) in ctx.headers.iter() {
+                req_build = req_build.header(k.as_str(), v.as_str());
+            }
+            if let Some(d) = &ctx.body {
+                req_build = req_build.body(d.clone());
+            }
+
+            req_build
+                .send()
+                .await
+                .map_err(|err| ReqError::from_reqwest(err, &ctx.url, &ctx.proxy))
+        })
+    }
+}
+
+static HTTP: Lazy<Arc<dyn Transport>> = Lazy::new(|| Arc::new(HttpTransport));
+
+static GLOBAL: Lazy<RwLock<Option<Arc<dyn Transport>>>> = Lazy::new(|| RwLock::new(None));
+
+tokio::task_local! {
+    static SCOPED: Arc<dyn Transport>;
+}
+
+/// 设置全局的发送层，None 时恢复为 HttpTransport
+pub fn set_global_transport(transport: Option<Arc<dyn Transport>>) {
+    *GLOBAL.write() = transport;
+}
+
+/// 在 fut 中使用指定的发送层，只对当前任务生效（fut 内 spawn 的任务不受影响），
+/// 并行执行的测试之间互不干扰
+pub async fn with_transport<F: Future>(transport: Arc<dyn Transport>, fut: F) -> F::Output {
+    SCOPED.scope(transport, fut).await
+}
+
+/// 当前任务使用的发送层
+pub fn current_transport() -> Arc<dyn Transport> {
+    SCOPED
+        .try_with(Arc::clone)
+        .ok()
+        .or_else(|| GLOBAL.read().clone())
+        .unwrap_or_else(|| HTTP.clone())
+}
@@ -0,0 +1,241 @@
+//! 带类型的请求：直接把响应反序列化为结构体
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use reqwest::Method;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use super::{spec_of, ReqError, ReqResponse};
+
+/// 错误信息中截取的内容长度（字符数）
+const SNIPPET_LEN: usize = 160;
+
+/// 响应内容格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyFormat {
+    Json,
+    Toml,
+}
+
+impl BodyFormat {
+    /// 优先按 Content-Type 判断，否则根据内容猜测
+    pub fn detect(headers: &HeaderMap, body: &str) -> Self {
+        let ct = headers
+            .get(CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if ct.contains("json") {
+            return BodyFormat::Json;
+        }
+        if ct.contains("toml") {
+            return BodyFormat::Toml;
+        }
+        Self::sniff(body)
+    }
+
+    /// 根据内容猜测：`{` 开头或 json 数组为 Json，`[table]` 或 `key = value` 为 Toml
+    pub fn sniff(body: &str) -> Self {
+        let t = body.trim_start();
+        if t.starts_with('{') {
+            return BodyFormat::Json;
+        }
+        if let Some(rest) = t.strip_prefix('[') {
+            let rest = rest.trim_start();
+            let table = rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '[')
+                && !["true", "false", "null"]
+                    .iter()
+                    .any(|w| rest.starts_with(w));
+            return if table {
+                BodyFormat::Toml
+            } else {
+                BodyFormat::Json
+            };
+        }
+        if t.starts_with('"') || t.is_empty() {
+            return BodyFormat::Json;
+        }
+        BodyFormat::Toml
+    }
+}
+
+/// 解析 json，出错时带上字段路径和出错位置附近的内容
+pub fn decode_json<T: DeserializeOwned>(url: &str, body: &str) -> Result<T, ReqError> {
+    let de = &mut serde_json::Deserializer::from_str(body);
+    serde_path_to_error::deserialize(de).map_err(|err| {
+        let inner = err.inner();
+        let pos = (inner.line() > 0).then(|| (inner.line() - 1, inner.column().saturating_sub(1)));
+        ReqError::Parse {
+            url: url.to_string(),
+            path: err.path().to_string(),
+            snippet: snippet(body, pos),
+            msg: inner.to_string(),
+        }
+    })
+}
+
+/// 解析 toml，出错时带上字段路径和出错位置附近的内容
+pub fn decode_toml<T: DeserializeOwned>(url: &str, body: &str) -> Result<T, ReqError> {
+    let de = &mut toml::Deserializer::new(body);
+    serde_path_to_error::deserialize(de).map_err(|err| ReqError::Parse {
+        url: url.to_string(),
+        path: err.path().to_string(),
+        snippet: snippet(body, err.inner().line_col()),
+        msg: err.inner().to_string(),
+    })
+}
+
+/// 按指定格式解析
+pub fn decode_as<T: DeserializeOwned>(
+    url: &str,
+    body: &str,
+    format: BodyFormat,
+) -> Result<T, ReqError> {
+    match format {
+        BodyFormat::Json => decode_json(url, body),
+        BodyFormat::Toml => decode_toml(url, body),
+    }
+}
+
+// 截取出错位置附近的内容；pos 为 (行, 列)，从 0 开始
+fn snippet(body: &str, pos: Option<(usize, usize)>) -> String {
+    let (line, col) = pos.unwrap_or_default();
+    let text = body.lines().nth(line).unwrap_or(body);
+    let chars = text.chars().collect::<Vec<_>>();
+    let start = col.min(chars.len()).saturating_sub(SNIPPET_LEN / 2);
+    let end = (start + SNIPPET_LEN).min(chars.len());
+    chars[start..end].iter().collect()
+}
+
+impl ReqResponse {
+    /// 按 Content-Type 或内容选择 json / toml 解析
+    pub fn typed<T: DeserializeOwned>(&self) -> Result<T, ReqError> {
+        let text = self.text();
+        decode_as(&self.url, &text, BodyFormat::detect(&self.headers, &text))
+    }
+}
+
+pub async fn get_json<T: DeserializeOwned>(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    timeout: Duration,
+) -> Result<T, ReqError> {
+    spec_of(Method::GET, url, req_head, proxy, timeout)
+        .send()
+        .await?
+        .json()
+}
+
+pub async fn post_json<B, T>(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    body: &B,
+    timeout: Duration,
+) -> Result<T, ReqError>
+where
+    B: Serialize + ?Sized,
+    T: DeserializeOwned,
+{
+    spec_of(Method::POST, url, req_head, proxy, timeout)
+        .json(body)
+        .send()
+        .await?
+        .json()
+}
+
+/// 根据响应的 Content-Type 或内容，自动选择 json / toml 解析
+pub async fn get_typed<T: DeserializeOwned>(
+    url: &str,
+    req_head: Option<&HashMap<String, String>>,
+    proxy: &Option<String>,
+    timeout: Duration,
+) -> Result<T, ReqError> {
+    spec_of(Method::GET, url, req_head, proxy, timeout)
+        .send()
+        .await?
+        .typed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Conf {
+        name: String,
+        ops: Vec<Op>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Op {
+        max: f64,
+    }
+
+    #[test]
+    fn sniff_format() {
+        assert_eq!(BodyFormat::sniff(r#"{"a":1}"#), BodyFormat::Json);
+        assert_eq!(BodyFormat::sniff(r#"["a","b"]"#), BodyFormat::Json);
+        assert_eq!(BodyFormat::sniff("[ true ]"), BodyFormat::Json);
+        assert_eq!(BodyFormat::sniff("[server]\nport = 1"), BodyFormat::Toml);
+        assert_eq!(BodyFormat::sniff("[[ops]]\nmax = 1"), BodyFormat::Toml);
+        assert_eq!(BodyFormat::sniff("name = \"a\""), BodyFormat::Toml);
+    }
+
+    #[test]
+    fn error_has_path_and_snippet() {
+        let body = r#"{"name":"a","ops":[{"max":1.0},{"max":"x"}]}"#;
+        match decode_json::<Conf>("u", body) {
+            Err(ReqError::Parse { path, snippet, .. }) => {
+                assert_eq!(path, "ops[1].max");
+                assert!(snippet.contains(r#""max":"x""#));
+            }
+            other => panic!("{:?}", other),
+        }
+
+        let body = "name = \"a\"\n[[ops]]\nmax = \"x\"\n";
+        match decode_toml::<Conf>("u", body) {
+            Err(ReqError::Parse { path, .. }) => assert_eq!(path, "ops[0].max"),
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn post_json_keeps_single_content_type() {
+        use std::sync::Arc;
+
+        use crate::tool::req::{with_transport, MockReply, MockTransport};
+
+        let url = "http://mock.invalid/order";
+        let mock =
+            Arc::new(MockTransport::new().on(Method::POST, url, MockReply::ok(r#"{"max":1}"#)));
+        let req_head = HashMap::from([(
+            "Content-Type".to_string(),
+            "application/json; charset=utf-8".to_string(),
+        )]);
+        let op: Op = with_transport(
+            mock.clone(),
+            post_json(url, Some(&req_head), &None, &[1], Duration::from_secs(1)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(op.max, 1.0);
+
+        let req = &mock.requests()[0];
+        let ct = req
+            .headers
+            .iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case("content-type"))
+            .map(|(_, v)| v.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ct, ["application/json; charset=utf-8"]);
+        assert_eq!(req.body.as_deref(), Some(b"[1]".as_slice()));
+    }
+}
@@ -0,0 +1,397 @@
+//! TLS 配置：证书校验、额外的根证书、客户端证书（mTLS）、按域名固定公钥（SPKI pin）
+//!
+//! 同时用于 `tool::req` 的 Client 和 `client::websocket` 的连接
+//!
+//! ```toml
+//! verify = true
+//! ca_files = ["./ca.pem"]
+//! client_cert = "./client.pem"
+//! client_key = "./client.key"
+//!
+//! [pins]
+//! "api.example.com" = ["sha256/wHXHBX6cMSlYoc2ovq2zAurMapG1cqaUEKpaLtPmDbU="]
+//! ```
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, Result};
+use data_encoding::BASE64;
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+use rustls::client::{ServerCertVerified, ServerCertVerifier, WebPkiVerifier};
+use rustls::{Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerName};
+use rustls_pemfile::Item;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use super::config::load_toml;
+
+static DEFAULT: Lazy<RwLock<TlsOptions>> = Lazy::new(|| RwLock::new(TlsOptions::default()));
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize)]
+#[serde(default)]
+pub struct TlsOptions {
+    /// 是否校验服务端证书，默认校验
+    pub verify: bool,
+    /// 额外信任的根证书，PEM 文件路径
+    pub ca_files: Vec<String>,
+    /// 客户端证书链，PEM 文件路径
+    pub client_cert: Option<String>,
+    /// 客户端私钥，PEM 文件路径
+    pub client_key: Option<String>,
+    /// 域名 -> 允许的服务端证书（leaf）公钥，格式 sha256/base64；域名可以写成 *.example.com
+    ///
+    /// 只匹配服务端自身的证书，中间证书是公开的，可以被附加到伪造的证书链中，不能作为 pin
+    pub pins: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for TlsOptions {
+    fn default() -> Self {
+        Self {
+            verify: true,
+            ca_files: Vec::new(),
+            client_cert: None,
+            client_key: None,
+            pins: BTreeMap::new(),
+        }
+    }
+}
+
+impl TlsOptions {
+    pub fn from_toml(content: &str) -> Result<Self> {
+        load_toml(content)
+    }
+
+    /// 不校验证书链；已配置 pin 的域名仍要求服务端证书公钥匹配，未配置 pin 的域名不做任何校验
+    pub fn insecure() -> Self {
+        Self::default().verify(false)
+    }
+
+    pub fn verify(mut self, verify: bool) -> Self {
+        self.verify = verify;
+        self
+    }
+
+    pub fn ca_file(mut self, path: impl Into<String>) -> Self {
+        self.ca_files.push(path.into());
+        self
+    }
+
+    pub fn client_cert(mut self, cert: impl Into<String>, key: impl Into<String>) -> Self {
+        self.client_cert = Some(cert.into());
+        self.client_key = Some(key.into());
+        self
+    }
+
+    pub fn pin(mut self, host: impl Into<String>, pin: impl Into<String>) -> Self {
+        self.pins.entry(host.into()).or_default().push(pin.into());
+        self
+    }
+
+    /// 是否与默认配置相同，相同时直接使用库自带的 TLS 配置
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// 生成 rustls 配置，不设置 ALPN
+    pub fn client_config(&self) -> Result<ClientConfig> {
+        let mut roots = RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+        for path in self.ca_files.iter() {
+            for cert in read_certs(path)? {
+                roots
+                    .add(&cert)
+                    .map_err(|err| anyhow!("根证书 {} 无效：{}", path, err))?;
+            }
+        }
+
+        let verifier = PinVerifier {
+            inner: self.verify.then(|| WebPkiVerifier::new(roots, None)),
+            pins: self.pins.clone(),
+        };
+        let builder = ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier));
+
+        match (&self.client_cert, &self.client_key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(read_certs(cert)?, read_key(key)?)
+                .map_err(|err| anyhow!("客户端证书 {} 无效：{}", cert, err)),
+            (None, None) => Ok(builder.with_no_client_auth()),
+            _ => Err(anyhow!("client_cert 和 client_key 需要同时配置")),
+        }
+    }
+
+    /// 用于 http 的配置，支持 h2
+    pub fn http_client_config(&self) -> Result<ClientConfig> {
+        let mut config = self.client_config()?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+}
+
+/// 全局默认的 TLS 配置，新建的 http Client 和 websocket 连接默认使用
+pub fn default_tls_options() -> TlsOptions {
+    DEFAULT.read().clone()
+}
+
+/// 修改全局默认的 TLS 配置，只对之后新建的 Client 生效
+pub fn set_default_tls_options(options: TlsOptions) {
+    *DEFAULT.write() = options;
+}
+
+/// 证书公钥的 pin：sha256/ + SubjectPublicKeyInfo 的 sha256（base64）
+pub fn spki_pin(cert_der: &[u8]) -> Option<String> {
+    let spki = spki_of(cert_der)?;
+    Some(format!("sha256/{}", BASE64.encode(&Sha256::digest(spki))))
+}
+
+fn read_certs(path: &str) -> Result<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut open(path)?)
+        .map_err(|err| anyhow!("读取证书 {} 失败：{}", path, err))?;
+    if certs.is_empty() {
+        return Err(anyhow!("证书文件 {} 中没有证书", path));
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn read_key(path: &str) -> Result<PrivateKey> {
+    let items = rustls_pemfile::read_all(&mut open(path)?)
+        .map_err(|err| anyhow!("读取私钥 {} 失败：{}", path, err))?;
+    items
+        .into_iter()
+        .find_map(|item| match item {
+            Item::RSAKey(k) | Item::PKCS8Key(k) | Item::ECKey(k) => Some(PrivateKey(k)),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("私钥文件 {} 中没有私钥", path))
+}
+
+fn open(path: &str) -> Result<BufReader<File>> {
+    let f = File::open(path).map_err(|err| anyhow!("打开 {} 失败：{}", path, err))?;
+    Ok(BufReader::new(f))
+}
+
+// 先按 webpki 校验证书链（verify 为 false 时跳过），再检查公钥 pin
+struct PinVerifier {
+    inner: Option<WebPkiVerifier>,
+    pins: BTreeMap<String, Vec<String>>,
+}
+
+impl PinVerifier {
+    fn pins_for(&self, host: &str) -> Option<&Vec<String>> {
+        self.pins.get(host).or_else(|| {
+            let (_, parent) = host.split_once('.')?;
+            self.pins.get(&format!("*.{}", parent))
+        })
+    }
+}
+
+impl ServerCertVerifier for PinVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        intermediates: &[Certificate],
+        server_name: &ServerName,
+        scts: &mut dyn Iterator<Item = &[u8]>,
+        ocsp_response: &[u8],
+        now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = match &self.inner {
+            Some(v) => v.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?,
+            None => ServerCertVerified::assertion(),
+        };
+
+        let host = match server_name {
+            ServerName::DnsName(d) => d.as_ref().to_string(),
+            ServerName::IpAddress(ip) => ip.to_string(),
+            _ => String::new(),
+        };
+        let pins = match self.pins_for(&host) {
+            Some(pins) => pins,
+            None => return Ok(verified),
+        };
+        // 只匹配服务端证书：握手时会用该公钥验证签名，对方必须持有私钥；
+        // 中间证书未必属于校验通过的证书链（verify 为 false 时没有校验）
+        let matched = spki_pin(&end_entity.0).is_some_and(|p| pins.contains(&p));
+        match matched {
+            true => Ok(verified),
+            false => Err(rustls::Error::General(format!(
+                "{} 的证书公钥与 pin 不匹配",
+                host
+            ))),
+        }
+    }
+}
+
+// 从 DER 证书中取出 SubjectPublicKeyInfo（包含 tag 和长度）
+//
+// Certificate ::= SEQUENCE { tbsCertificate, ... }
+// TBSCertificate ::= SEQUENCE { [0] version OPTIONAL, serialNumber, signature,
+//                               issuer, validity, subject, subjectPublicKeyInfo, ... }
+fn spki_of(der: &[u8]) -> Option<&[u8]> {
+    let (_, cert, _) = der_tlv(der)?;
+    let (_, tbs, _) = der_tlv(cert)?;
+    let mut rest = tbs;
+    let (tag, _, _) = der_tlv(rest)?;
+    if tag == 0xa0 {
+        rest = der_tlv(rest)?.2;
+    }
+    // 跳过 serialNumber、signature、issuer、validity、subject
+    for _ in 0..5 {
+        rest = der_tlv(rest)?.2;
+    }
+    let (_, _, after) = der_tlv(rest)?;
+    Some(&rest[..rest.len() - after.len()])
+}
+
+// 解析一个 DER 元素，返回 (tag, 内容, 剩余部分)
+fn der_tlv(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let tag = *data.first()?;
+    let first = *data.get(1)? as usize;
+    let (len, header) = if first < 0x80 {
+        (first, 2)
+    } else {
+        let n = first & 0x7f;
+        if n == 0 || n > 4 {
+            return None;
+        }
+        let len = data
+            .get(2..2 + n)?
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (len, 2 + n)
+    };
+    let end = header.checked_add(len)?;
+    Some((tag, data.get(header..end)?, data.get(end..)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CERT: &str = "-----BEGIN CERTIFICATE-----
+MIIBfTCCASOgAwIBAgIUHy31/v8pW3X8AI5+CaS+xnwtHEQwCgYIKoZIzj0EAwIw
+EzERMA8GA1UEAwwIcGluLnRlc3QwIBcNMjYxMDE4MDY0ODM5WhgPMjEyNjA5MjQw
+NjQ4MzlaMBMxETAPBgNVBAMMCHBpbi50ZXN0MFkwEwYHKoZIzj0CAQYIKoZIzj0D
+AQcDQgAEKX4L7mLr8iVrAIvQnGpIndx5SCSSudPcJeN1TnwvcoT5H3z6kZXamKWQ
+SLMksWjsZKNe8eauTDsGsS8Jr5UgW6NTMFEwHQYDVR0OBBYEFPo4cMbXvKAwkMMI
+A36vQ60ScO3pMB8GA1UdIwQYMBaAFPo4cMbXvKAwkMMIA36vQ60ScO3pMA8GA1Ud
+EwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIgQDcuj2W6U6c1pzomR84GSm8F
+oleITTKFhyaEZlEwFQcCIQDh0ZnQnxsHPWOSmak+NQF+5mnD6C70LjSiB4TfKVUJ
+Rw==
+-----END CERTIFICATE-----
+";
+
+    #[test]
+    fn pin_of_cert() {
+        let der = rustls_pemfile::certs(&mut CERT.as_bytes())
+            .unwrap()
+            .remove(0);
+        // openssl x509 -pubkey -noout | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64
+        assert_eq!(
+            spki_pin(&der).unwrap(),
+            "sha256/wHXHBX6cMSlYoc2ovq2zAurMapG1cqaUEKpaLtPmDbU="
+        );
+    }
+
+    // 与 CERT 的公钥不同
+    const FORGED: &str = "-----BEGIN CERTIFICATE-----
+MIIBfDCCASOgAwIBAgIUCy5o8Uyi6v2AM7MmdK8zq+Z8vVswCgYIKoZIzj0EAwIw
+EzERMA8GA1UEAwwIcGluLnRlc3QwIBcNMjYxMDE4MDczMjM4WhgPMjEyNjA5MjQw
+NzMyMzhaMBMxETAPBgNVBAMMCHBpbi50ZXN0MFkwEwYHKoZIzj0CAQYIKoZIzj0D
+AQcDQgAEjf7Eg3hYCURZBmI574yb6Me6hiAIlVdNV4nfdHjWPIWj2ghfgxYDnIMF
+mS5Z3xPGlCmWRF1bJGMYjHDk4LQXfaNTMFEwHQYDVR0OBBYEFBJDNM+FAMd1n+VV
+L/A61efSAlkoMB8GA1UdIwQYMBaAFBJDNM+FAMd1n+VVL/A61efSAlkoMA8GA1Ud
+EwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDRwAwRAIgWpYaJJFDoeTmRgmMSBc0Miji
+VJyCcTX7pJ3t1OcNLVQCIGqRLMxaXZMYostErpDAO/5GJ7TzZysGZOQgIWSfsrPy
+-----END CERTIFICATE-----
+";
+
+    fn cert_of(pem: &str) -> Certificate {
+        Certificate(
+            rustls_pemfile::certs(&mut pem.as_bytes())
+                .unwrap()
+                .remove(0),
+        )
+    }
+
+    #[test]
+    fn pin_verifier() {
+        let cert = cert_of(CERT);
+        let verify = |opts: TlsOptions, host: &str| {
+            let v = PinVerifier {
+                inner: None,
+                pins: opts.pins,
+            };
+            let name = ServerName::try_from(host).unwrap();
+            v.verify_server_cert(
+                &cert,
+                &[],
+                &name,
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+            .is_ok()
+        };
+        let pin = "sha256/wHXHBX6cMSlYoc2ovq2zAurMapG1cqaUEKpaLtPmDbU=";
+
+        assert!(verify(
+            TlsOptions::insecure().pin("pin.test", pin),
+            "pin.test"
+        ));
+        assert!(verify(TlsOptions::insecure().pin("*.test", pin), "a.test"));
+        assert!(!verify(
+            TlsOptions::insecure().pin("pin.test", "sha256/AAAA"),
+            "pin.test"
+        ));
+        assert!(verify(
+            TlsOptions::insecure().pin("pin.test", "sha256/AAAA"),
+            "other.test"
+        ));
+    }
+
+    // 伪造的服务端证书 + 真实的被 pin 证书作为中间证书，不能通过
+    #[test]
+    fn pin_ignores_intermediates() {
+        let (real, forged) = (cert_of(CERT), cert_of(FORGED));
+        let v = PinVerifier {
+            inner: None,
+            pins: TlsOptions::insecure()
+                .pin("pin.test", spki_pin(&real.0).unwrap())
+                .pins,
+        };
+        let name = ServerName::try_from("pin.test").unwrap();
+        let verify = |leaf: &Certificate, intermediates: &[Certificate]| {
+            v.verify_server_cert(
+                leaf,
+                intermediates,
+                &name,
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+            .is_ok()
+        };
+        assert!(!verify(&forged, std::slice::from_ref(&real)));
+        assert!(verify(&real, std::slice::from_ref(&forged)));
+    }
+}